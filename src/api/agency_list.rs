@@ -1,6 +1,7 @@
 //! Module for handling agency functions
 
-use error::Error;
+use NextBus;
+use api::Partial;
 use request::{Command, Request};
 use std::io::Read;
 use xml::reader::{EventReader, XmlEvent};
@@ -8,6 +9,7 @@ use xml::reader::{EventReader, XmlEvent};
 /// List of Agencies. Maps directly from Nextbus
 /// response. Contains vec of routes.
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct AgencyList(Vec<Agency>);
 
 impl AgencyList {
@@ -39,36 +41,61 @@ impl<'a> IntoIterator for &'a AgencyList {
 // ===============================================================
 
 /// Builds a request for AgencyList.
-/// Since there's no config, it's empty,
+/// Since there's no config, it only holds the client,
 /// but it's a consistent API with other commands.
-pub struct AgencyListBuilder;
+pub struct AgencyListBuilder {
+    client: NextBus,
+}
 
 impl AgencyListBuilder {
 
-    pub fn new() -> Self {
-        AgencyListBuilder
+    pub fn new(client: NextBus) -> Self {
+        AgencyListBuilder { client: client }
+    }
+
+    /// Builder to set the base url for this request only,
+    /// overriding the client's.
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.client.base_url = base_url.to_owned();
+        self
     }
 
     pub fn get(self) -> ::Result<AgencyList> {
+        self.get_partial().and_then(Partial::into_result)
+    }
 
-        // Make the request
-        let res = try!(Request::new()
-            .command(Command::AgencyList)
-            .send());
+    /// Like get, but if the response breaks off (e.g. the body is
+    /// cut short), returns the agencies parsed before then, with a
+    /// warning.
+    pub fn get_partial(self) -> ::Result<Partial<AgencyList>> {
 
-        // Parse xml into agency list struct
-        Self::from_xml(res)
+        // Make the request, and parse xml into agency list struct
+        self.client.get(Request::new()
+            .command(Command::AgencyList), Self::from_xml_partial)
     }
 
-    fn from_xml<R: Read>(input: R) -> ::Result<AgencyList> {
+    #[cfg(test)]
+    pub(super) fn from_xml<R: Read>(input: R) -> ::Result<AgencyList> {
+        Self::from_xml_partial(input).and_then(Partial::into_result)
+    }
+
+    fn from_xml_partial<R: Read>(input: R) -> ::Result<Partial<AgencyList>> {
         // Vec for collecting agencies
         let mut agencies = vec![];
+        let res = Self::parse_agencies(input, &mut agencies);
+        Partial::from_result(AgencyList(agencies), res)
+    }
 
-        let parser = EventReader::new(input);
+    fn parse_agencies<R: Read>(input: R, agencies: &mut Vec<Agency>) -> ::Result<()> {
+        let mut parser = EventReader::new(input);
 
-        for event in parser {
-            match event {
+        loop {
+            match parser.next() {
                 Ok(XmlEvent::StartElement {name, attributes, ..}) => {
+                    if name.borrow().local_name == "Error" {
+                        return Err(super::parse_api_error(&mut parser, attributes));
+                    }
+
                     if name.borrow().local_name == "agency" {
                         let mut tag = None ;
                         let mut title = None;
@@ -90,18 +117,20 @@ impl AgencyListBuilder {
                         }
 
                         agencies.push(Agency{
-                            tag: try!(tag.ok_or(Error::ParseError)),
-                            title: try!(title.ok_or(Error::ParseError)),
+                            tag: try!(super::required(tag, "agency", "tag")),
+                            title: try!(super::required(title, "agency", "title")),
                             short_title: short_title,
-                            region_title: try!(region_title.ok_or(Error::ParseError)),
+                            region_title: try!(super::required(region_title, "agency", "regionTitle")),
                         });
                     }
                 },
-                _ => (),
+                Ok(XmlEvent::EndDocument) => break,
+                Ok(_) => continue,
+                Err(err) => return Err(err.into()),
             }
         }
 
-        Ok(AgencyList(agencies))
+        Ok(())
     }
 }
 
@@ -109,6 +138,8 @@ impl AgencyListBuilder {
 // ===============================================================
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub struct Agency {
     tag: String,
     title: String,
@@ -128,6 +159,29 @@ impl Agency {
             region_title: region_title,
         }
     }
+
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn short_title(&self) -> Option<&str> {
+        self.short_title.as_ref().map(String::as_str)
+    }
+
+    /// Title to show in at most `max_len` characters, e.g. 16 on an
+    /// LED sign: the title if it fits, else the short title if it's
+    /// shorter. Neither is cut to fit.
+    pub fn display_title(&self, max_len: usize) -> &str {
+        super::display_title(&self.title, self.short_title(), max_len)
+    }
+
+    pub fn region_title(&self) -> &str {
+        &self.region_title
+    }
 }
 
 // Tests
@@ -136,6 +190,7 @@ impl Agency {
 #[cfg(test)]
 mod test {
     use std::io::Cursor;
+    use transport::fixture_client;
     use super::*;
 
     // test xml
@@ -189,6 +244,33 @@ mod test {
         assert_eq!(agencies, test_agencies);
     }
 
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_round_trip() {
+        use serde_json;
+
+        let buffer = Cursor::new(GOOD_AGENCY_XML);
+        let agencies = AgencyListBuilder::from_xml(buffer).unwrap();
+
+        // Field names match the Nextbus attributes
+        let json = serde_json::to_string(&agencies).unwrap();
+        assert!(json.contains("\"shortTitle\":\"Camarillo (CAT)\""));
+        assert!(json.contains("\"regionTitle\":\"Maryland\""));
+
+        let from_json: AgencyList = serde_json::from_str(&json).unwrap();
+        assert_eq!(from_json, agencies);
+    }
+
+    #[test]
+    fn agency_display_title() {
+        let camarillo = Agency::new("camarillo".to_owned(),
+                                    "Camarillo Area (CAT)".to_owned(),
+                                    Some("Camarillo (CAT)".to_owned()),
+                                    "California-Southern".to_owned());
+        assert_eq!(camarillo.display_title(16), "Camarillo (CAT)");
+        assert_eq!(camarillo.display_title(20), "Camarillo Area (CAT)");
+    }
+
     #[test]
     #[should_panic]
     fn parse_bad_xml_missing_tag() {
@@ -226,14 +308,10 @@ mod test {
     }
 
     #[test]
-    #[ignore]
     fn should_get_agencies() {
-        let agencies = AgencyListBuilder::new().get().unwrap();
-        for agency in agencies {
-            println!("agency");
-            println!("{:?}\n", agency);
-        }
-        assert!(false);
+        let agencies = AgencyListBuilder::new(fixture_client()).get().unwrap();
+        let tags: Vec<_> = agencies.into_iter().map(|agency| agency.tag().to_owned()).collect();
+        assert_eq!(tags, vec!["jhu-apl", "camarillo", "mbta", "mit", "moorpark", "sf-muni"]);
     }
 }
 
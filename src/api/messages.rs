@@ -0,0 +1,722 @@
+//! Next Bus Messages Command
+
+use NextBus;
+use api::Partial;
+use error::Error;
+use request::{Command, Request};
+use std::io::Read;
+use xml::attribute::OwnedAttribute;
+use xml::reader::{EventReader, XmlEvent};
+
+/// Messages for a list of routes. Maps directly from Nextbus response.
+/// Agency-wide messages are under the route with tag "all".
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Messages(Vec<Route>);
+
+impl Messages {
+    pub fn new(routes: Vec<Route>) -> Self {
+        Messages(routes)
+    }
+}
+
+impl IntoIterator for Messages {
+    type Item = Route;
+    type IntoIter = ::std::vec::IntoIter<Route>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Messages {
+    type Item = &'a Route;
+    type IntoIter = ::std::slice::Iter<'a, Route>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let &Messages(ref routes) = self;
+        routes.iter()
+    }
+}
+
+// Builder
+// ===============================================================
+
+pub struct MessagesBuilder<'a> {
+    client: NextBus,
+    agency: Option<&'a str>,
+    routes: Vec<&'a str>,
+}
+
+impl<'a> MessagesBuilder<'a> {
+    pub fn new(client: NextBus) -> Self {
+        MessagesBuilder {
+            client: client,
+            agency: None,
+            routes: Vec::new(),
+        }
+    }
+
+    /// Builder to set the base url for this request only,
+    /// overriding the client's.
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.client.base_url = base_url.to_owned();
+        self
+    }
+
+    /// Builder to set agency
+    pub fn agency(mut self, agency: &'a str) -> Self {
+        self.agency = Some(agency);
+        self
+    }
+
+    /// Builder to add a route. Can be called multiple times
+    /// to get messages for many routes at once.
+    pub fn route(mut self, route: &'a str) -> Self {
+        self.routes.push(route);
+        self
+    }
+
+    pub fn get(&self) -> ::Result<Messages> {
+        self.get_partial().and_then(Partial::into_result)
+    }
+
+    /// Like get, but if the response breaks off (e.g. the body is
+    /// cut short), returns the routes parsed before then, with a
+    /// warning.
+    pub fn get_partial(&self) -> ::Result<Partial<Messages>> {
+        // Check if agency is none. If so, send error.
+        let agency = try!(self.client.agency_or_default(self.agency));
+
+        // Request. Allow many routes or no route param (returns all routes)
+        let mut request = Request::new();
+        request.command(Command::Messages).agency(agency);
+        if !self.routes.is_empty() {
+            request.routes(self.routes.clone());
+        }
+
+        // Parse xml into messages struct
+        self.client.get(&request, Self::from_xml_partial)
+    }
+
+    #[cfg(test)]
+    pub(super) fn from_xml<R: Read>(input: R) -> ::Result<Messages> {
+        Self::from_xml_partial(input).and_then(Partial::into_result)
+    }
+
+    fn from_xml_partial<R: Read>(input: R) -> ::Result<Partial<Messages>> {
+        // Vec for collecting routes
+        let mut routes = vec![];
+        let res = Self::parse_routes(input, &mut routes);
+        Partial::from_result(Messages(routes), res)
+    }
+
+    fn parse_routes<R: Read>(input: R, routes: &mut Vec<Route>) -> ::Result<()> {
+        let mut parser = EventReader::new(input);
+
+        loop {
+            match parser.next() {
+                Ok(XmlEvent::StartElement {name, attributes, ..}) => {
+                    if name.borrow().local_name == "Error" {
+                        return Err(super::parse_api_error(&mut parser, attributes));
+                    }
+
+                    if name.borrow().local_name == "route" {
+                        try!(add_route_to_routes(&mut parser, attributes, routes));
+                    }
+                },
+                Ok(XmlEvent::EndDocument) => break,
+                Ok(_) => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Components of Messages
+// ===============================================================
+
+/// The messages for one route
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Route {
+    tag: String,
+    messages: Vec<Message>,
+}
+
+impl Route {
+    pub fn new(tag: String, messages: Vec<Message>) -> Self {
+        Route {
+            tag: tag,
+            messages: messages,
+        }
+    }
+
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+}
+
+/// A message, with when and where it's valid.
+///
+/// Boundaries are milliseconds since the epoch. If there are no
+/// configured routes, the message applies to the whole route.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Message {
+    id: String,
+    creator: Option<String>,
+    priority: String,
+    send_to_buses: bool,
+    start_boundary: Option<usize>,
+    start_boundary_str: Option<String>,
+    end_boundary: Option<usize>,
+    end_boundary_str: Option<String>,
+    text: String,
+    text_secondary_language: Option<String>,
+    phoneme_text: Option<String>,
+    configured_routes: Vec<ConfiguredRoute>,
+    intervals: Vec<Interval>,
+}
+
+impl Message {
+    pub fn new(id: String,
+               creator: Option<String>,
+               priority: String,
+               send_to_buses: bool,
+               start_boundary: Option<usize>,
+               start_boundary_str: Option<String>,
+               end_boundary: Option<usize>,
+               end_boundary_str: Option<String>,
+               text: String,
+               text_secondary_language: Option<String>,
+               phoneme_text: Option<String>,
+               configured_routes: Vec<ConfiguredRoute>,
+               intervals: Vec<Interval>) -> Self {
+        Message {
+            id: id,
+            creator: creator,
+            priority: priority,
+            send_to_buses: send_to_buses,
+            start_boundary: start_boundary,
+            start_boundary_str: start_boundary_str,
+            end_boundary: end_boundary,
+            end_boundary_str: end_boundary_str,
+            text: text,
+            text_secondary_language: text_secondary_language,
+            phoneme_text: phoneme_text,
+            configured_routes: configured_routes,
+            intervals: intervals,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn creator(&self) -> Option<&str> {
+        self.creator.as_ref().map(String::as_str)
+    }
+
+    pub fn priority(&self) -> &str {
+        &self.priority
+    }
+
+    pub fn send_to_buses(&self) -> bool {
+        self.send_to_buses
+    }
+
+    pub fn start_boundary(&self) -> Option<usize> {
+        self.start_boundary
+    }
+
+    pub fn start_boundary_str(&self) -> Option<&str> {
+        self.start_boundary_str.as_ref().map(String::as_str)
+    }
+
+    pub fn end_boundary(&self) -> Option<usize> {
+        self.end_boundary
+    }
+
+    pub fn end_boundary_str(&self) -> Option<&str> {
+        self.end_boundary_str.as_ref().map(String::as_str)
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn text_secondary_language(&self) -> Option<&str> {
+        self.text_secondary_language.as_ref().map(String::as_str)
+    }
+
+    pub fn phoneme_text(&self) -> Option<&str> {
+        self.phoneme_text.as_ref().map(String::as_str)
+    }
+
+    pub fn configured_routes(&self) -> &[ConfiguredRoute] {
+        &self.configured_routes
+    }
+
+    pub fn intervals(&self) -> &[Interval] {
+        &self.intervals
+    }
+}
+
+/// Scopes a message to a route, and optionally to some of its stops
+/// (`routeConfiguredForMessage` in the Nextbus response).
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct ConfiguredRoute {
+    tag: String,
+    stops: Vec<Stop>,
+}
+
+impl ConfiguredRoute {
+    pub fn new(tag: String, stops: Vec<Stop>) -> Self {
+        ConfiguredRoute {
+            tag: tag,
+            stops: stops,
+        }
+    }
+
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn stops(&self) -> &[Stop] {
+        &self.stops
+    }
+}
+
+/// A stop the message is configured for
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Stop {
+    tag: String,
+    title: Option<String>,
+}
+
+impl Stop {
+    pub fn new(tag: String, title: Option<String>) -> Self {
+        Stop {
+            tag: tag,
+            title: title,
+        }
+    }
+
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_ref().map(String::as_str)
+    }
+}
+
+/// A weekly window in which the message is active.
+/// Days are 0 (Sunday) to 6, times are seconds into the day.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Interval {
+    start_day: usize,
+    start_time: usize,
+    end_day: usize,
+    end_time: usize,
+}
+
+impl Interval {
+    pub fn new(start_day: usize,
+               start_time: usize,
+               end_day: usize,
+               end_time: usize) -> Self {
+        Interval {
+            start_day: start_day,
+            start_time: start_time,
+            end_day: end_day,
+            end_time: end_time,
+        }
+    }
+
+    pub fn start_day(&self) -> usize {
+        self.start_day
+    }
+
+    pub fn start_time(&self) -> usize {
+        self.start_time
+    }
+
+    pub fn end_day(&self) -> usize {
+        self.end_day
+    }
+
+    pub fn end_time(&self) -> usize {
+        self.end_time
+    }
+}
+
+// ===============================================================
+// Helpers for parsing
+// ===============================================================
+
+// Parsing a Route
+// ===============================================================
+
+fn add_route_to_routes<R: Read>(mut parser: &mut EventReader<R>,
+                                attributes: Vec<OwnedAttribute>,
+                                routes: &mut Vec<Route>) -> ::Result<()> {
+    let mut tag = None;
+
+    for attribute in attributes {
+        let attribute = attribute.borrow();
+        let name = attribute.name.local_name;
+        let value = attribute.value;
+
+        match name {
+            "tag" => tag = Some(value.to_owned()),
+            _ => (),
+        };
+    }
+
+    let mut messages = Vec::new();
+    loop {
+        match parser.next() {
+            Ok(XmlEvent::StartElement {name, attributes, ..}) => {
+                if name.borrow().local_name == "message" {
+                    try!(add_message_to_messages(&mut parser, attributes, &mut messages));
+                }
+            },
+            Ok(XmlEvent::EndElement {name, ..}) => {
+                if name.borrow().local_name == "route" {
+                    break;
+                }
+            },
+            Err(err) => return Err(err.into()),
+            _ => continue,
+        }
+    }
+
+    routes.push(Route {
+        tag: try!(super::required(tag, "route", "tag")),
+        messages: messages,
+    });
+    Ok(())
+}
+
+// Parsing a Message
+// ===============================================================
+
+fn add_message_to_messages<R: Read>(mut parser: &mut EventReader<R>,
+                                    attributes: Vec<OwnedAttribute>,
+                                    messages: &mut Vec<Message>) -> ::Result<()> {
+    let mut id = None;
+    let mut creator = None;
+    let mut priority = None;
+    let mut send_to_buses: Option<bool> = None;
+    let mut start_boundary: Option<usize> = None;
+    let mut start_boundary_str = None;
+    let mut end_boundary: Option<usize> = None;
+    let mut end_boundary_str = None;
+
+    for attribute in attributes {
+        let attribute = attribute.borrow();
+        let name = attribute.name.local_name;
+        let value = attribute.value;
+
+        match name {
+            "id" => id = Some(value.to_owned()),
+            "creator" => creator = Some(value.to_owned()),
+            "priority" => priority = Some(value.to_owned()),
+            "sendToBuses" =>
+                send_to_buses = Some(try!(super::parse_value(value, "message", "sendToBuses"))),
+            "startBoundary" =>
+                start_boundary = Some(try!(super::parse_value(value, "message", "startBoundary"))),
+            "startBoundaryStr" => start_boundary_str = Some(value.to_owned()),
+            "endBoundary" =>
+                end_boundary = Some(try!(super::parse_value(value, "message", "endBoundary"))),
+            "endBoundaryStr" => end_boundary_str = Some(value.to_owned()),
+            _ => (),
+        };
+    }
+
+    // Now iterate through the nested elements
+    // The logic for matching:
+    // - text elements are read until their end tag
+    // - routeConfiguredForMessage and interval are parsed into structs
+    // - break if it's an end element for message
+    // - return on error
+    // - continue if it's any other event (whitespace, etc.)
+    let mut text = None;
+    let mut text_secondary_language = None;
+    let mut phoneme_text = None;
+    let mut configured_routes = Vec::new();
+    let mut intervals = Vec::new();
+    loop {
+        match parser.next() {
+            Ok(XmlEvent::StartElement {name, attributes, ..}) => {
+                let name = name.borrow().local_name;
+                if name == "text" {
+                    text = Some(try!(super::parse_text(&mut parser)));
+                } else if name == "textSecondaryLanguage" {
+                    text_secondary_language = Some(try!(super::parse_text(&mut parser)));
+                } else if name == "phonemeText" {
+                    phoneme_text = Some(try!(super::parse_text(&mut parser)));
+                } else if name == "routeConfiguredForMessage" {
+                    try!(add_configured_route(&mut parser, attributes, &mut configured_routes));
+                } else if name == "interval" {
+                    try!(add_interval_to_intervals(attributes, &mut intervals));
+                }
+            },
+            Ok(XmlEvent::EndElement {name, ..}) => {
+                if name.borrow().local_name == "message" {
+                    break;
+                }
+            },
+            Err(err) => return Err(err.into()),
+            _ => continue,
+        }
+    }
+
+    messages.push(Message {
+        id: try!(super::required(id, "message", "id")),
+        creator: creator,
+        priority: try!(super::required(priority, "message", "priority")),
+        send_to_buses: try!(super::required(send_to_buses, "message", "sendToBuses")),
+        start_boundary: start_boundary,
+        start_boundary_str: start_boundary_str,
+        end_boundary: end_boundary,
+        end_boundary_str: end_boundary_str,
+        text: try!(text.ok_or(Error::MissingElement("text"))),
+        text_secondary_language: text_secondary_language,
+        phoneme_text: phoneme_text,
+        configured_routes: configured_routes,
+        intervals: intervals,
+    });
+    Ok(())
+}
+
+fn add_configured_route<R: Read>(parser: &mut EventReader<R>,
+                                 attributes: Vec<OwnedAttribute>,
+                                 configured_routes: &mut Vec<ConfiguredRoute>) -> ::Result<()> {
+    let mut tag = None;
+
+    for attribute in attributes {
+        let attribute = attribute.borrow();
+        let name = attribute.name.local_name;
+        let value = attribute.value;
+
+        match name {
+            "tag" => tag = Some(value.to_owned()),
+            _ => (),
+        };
+    }
+
+    let mut stops = Vec::new();
+    loop {
+        match parser.next() {
+            Ok(XmlEvent::StartElement {name, attributes, ..}) => {
+                if name.borrow().local_name == "stop" {
+                    try!(add_stop_to_stops(attributes, &mut stops));
+                }
+            },
+            Ok(XmlEvent::EndElement {name, ..}) => {
+                if name.borrow().local_name == "routeConfiguredForMessage" {
+                    break;
+                }
+            },
+            Err(err) => return Err(err.into()),
+            _ => continue,
+        }
+    }
+
+    configured_routes.push(ConfiguredRoute {
+        tag: try!(super::required(tag, "routeConfiguredForMessage", "tag")),
+        stops: stops,
+    });
+    Ok(())
+}
+
+fn add_stop_to_stops(attributes: Vec<OwnedAttribute>,
+                     stops: &mut Vec<Stop>) -> ::Result<()> {
+    let mut tag = None;
+    let mut title = None;
+
+    for attribute in attributes {
+        let attribute = attribute.borrow();
+        let name = attribute.name.local_name;
+        let value = attribute.value;
+
+        match name {
+            "tag" => tag = Some(value.to_owned()),
+            "title" => title = Some(value.to_owned()),
+            _ => (),
+        };
+    }
+    stops.push(Stop {
+        tag: try!(super::required(tag, "stop", "tag")),
+        title: title,
+    });
+    Ok(())
+}
+
+fn add_interval_to_intervals(attributes: Vec<OwnedAttribute>,
+                             intervals: &mut Vec<Interval>) -> ::Result<()> {
+    let mut start_day: Option<usize> = None;
+    let mut start_time: Option<usize> = None;
+    let mut end_day: Option<usize> = None;
+    let mut end_time: Option<usize> = None;
+
+    for attribute in attributes {
+        let attribute = attribute.borrow();
+        let name = attribute.name.local_name;
+        let value = attribute.value;
+
+        match name {
+            "startDay" =>
+                start_day = Some(try!(super::parse_value(value, "interval", "startDay"))),
+            "startTime" =>
+                start_time = Some(try!(super::parse_value(value, "interval", "startTime"))),
+            "endDay" => end_day = Some(try!(super::parse_value(value, "interval", "endDay"))),
+            "endTime" => end_time = Some(try!(super::parse_value(value, "interval", "endTime"))),
+            _ => (),
+        };
+    }
+    intervals.push(Interval {
+        start_day: try!(super::required(start_day, "interval", "startDay")),
+        start_time: try!(super::required(start_time, "interval", "startTime")),
+        end_day: try!(super::required(end_day, "interval", "endDay")),
+        end_time: try!(super::required(end_time, "interval", "endTime")),
+    });
+    Ok(())
+}
+
+// Tests
+// ===============================================================
+
+#[cfg(test)]
+mod test {
+    use api::test::assert_invalid_attributes;
+    use std::io::Cursor;
+    use transport::fixture_client;
+    use super::*;
+
+    const GOOD_MESSAGES_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright San Francisco Muni 2016.\">
+        <route tag=\"all\">
+        <message id=\"15395\" creator=\"jdoe\" startBoundary=\"1378408800000\"
+            startBoundaryStr=\"Thu, Sep 05 12:20:00 PDT 2013\" sendToBuses=\"false\"
+            priority=\"Normal\">
+        <text>Sign up for alerts</text>
+        </message>
+        </route>
+        <route tag=\"N\">
+        <message id=\"16121\" sendToBuses=\"true\" priority=\"High\">
+        <routeConfiguredForMessage tag=\"N\">
+        <stop tag=\"5555\" title=\"Judah St &amp; 9th Ave\"/>
+        </routeConfiguredForMessage>
+        <interval startDay=\"1\" startTime=\"25200\" endDay=\"1\" endTime=\"32400\"/>
+        <text>Stop moved</text>
+        <phonemeText>Stop moved</phonemeText>
+        </message>
+        </route>
+        </body>";
+
+    const MISSING_TEXT_MESSAGES_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright San Francisco Muni 2016.\">
+        <route tag=\"all\">
+        <message id=\"15395\" sendToBuses=\"false\" priority=\"Normal\">
+        </message>
+        </route>
+        </body>";
+
+    #[test]
+    fn parse_good_xml() {
+        let buffer = Cursor::new(GOOD_MESSAGES_XML);
+        let messages = MessagesBuilder::from_xml(buffer).unwrap();
+
+        let test_all = Route::new("all".to_owned(), vec![
+            Message::new("15395".to_owned(),
+                         Some("jdoe".to_owned()),
+                         "Normal".to_owned(),
+                         false,
+                         Some(1378408800000),
+                         Some("Thu, Sep 05 12:20:00 PDT 2013".to_owned()),
+                         None,
+                         None,
+                         "Sign up for alerts".to_owned(),
+                         None,
+                         None,
+                         vec![],
+                         vec![]),
+        ]);
+        let test_n = Route::new("N".to_owned(), vec![
+            Message::new("16121".to_owned(),
+                         None,
+                         "High".to_owned(),
+                         true,
+                         None,
+                         None,
+                         None,
+                         None,
+                         "Stop moved".to_owned(),
+                         None,
+                         Some("Stop moved".to_owned()),
+                         vec![ConfiguredRoute::new("N".to_owned(), vec![
+                             Stop::new("5555".to_owned(),
+                                       Some("Judah St & 9th Ave".to_owned())),
+                         ])],
+                         vec![Interval::new(1, 25200, 1, 32400)]),
+        ]);
+        let test_messages = Messages::new(vec![test_all, test_n]);
+
+        assert_eq!(messages, test_messages);
+    }
+
+    #[test]
+    #[should_panic]
+    fn parse_bad_xml_missing_text() {
+        let buffer = Cursor::new(MISSING_TEXT_MESSAGES_XML);
+        MessagesBuilder::from_xml(buffer).unwrap();
+    }
+
+    #[test]
+    fn parse_invalid_attribute() {
+        // Corrupted value in the response, and where it should fail
+        let cases = vec![
+            ("sendToBuses=\"false\"", "sendToBuses=\"0\"", "message", "sendToBuses"),
+            ("startBoundary=\"1378408800000\"", "startBoundary=\"soon\"", "message", "startBoundary"),
+            ("startDay=\"1\"", "startDay=\"Mon\"", "interval", "startDay"),
+            ("startTime=\"25200\"", "startTime=\"7:00\"", "interval", "startTime"),
+            ("endDay=\"1\"", "endDay=\"-1\"", "interval", "endDay"),
+            ("endTime=\"32400\"", "endTime=\"\"", "interval", "endTime"),
+        ];
+        assert_invalid_attributes(GOOD_MESSAGES_XML, cases, MessagesBuilder::from_xml);
+    }
+
+    #[test]
+    fn should_get_messages() {
+        let messages = MessagesBuilder::new(fixture_client())
+            .agency("sf-muni")
+            .route("N")
+            .route("J")
+            .get()
+            .unwrap();
+        let counts: Vec<_> = messages.into_iter()
+            .map(|route| (route.tag().to_owned(), route.messages().len()))
+            .collect();
+        assert_eq!(counts, vec![("all".to_owned(), 1), ("N".to_owned(), 1), ("J".to_owned(), 0)]);
+    }
+}
@@ -15,8 +15,324 @@ pub mod agency_list;
 pub mod route_list;
 pub mod route_config;
 pub mod predictions;
-//pub mod predictions_for_multi_stops;
-//pub mod schedule;
-//pub mod messages;
-//pub mod vehicle_locations;
+pub mod predictions_for_multi_stops;
+pub mod schedule;
+pub mod messages;
+pub mod vehicle_locations;
 
+
+use error::Error;
+use std::io::Read;
+use std::str::FromStr;
+use xml::attribute::OwnedAttribute;
+use xml::reader::{EventReader, XmlEvent};
+
+// Required parameters
+// ===============================================================
+
+/// Type parameter of a builder for a required parameter which
+/// hasn't been set. Only builders with every one of them set have a
+/// `get`, so leaving one out is a compile error rather than an
+/// `Error::MissingParameter`.
+///
+/// The agency isn't one of them, since it can be the client's.
+#[derive(Clone, Copy, Debug)]
+pub struct Unset;
+
+/// Type parameter of a builder for a required parameter which
+/// has been set, holding its value.
+#[derive(Clone, Copy, Debug)]
+pub struct Set<T>(pub T);
+
+// Partial responses
+// ===============================================================
+
+/// A response parsed up to where its XML broke off, e.g. when the
+/// body was cut short. Holds what was parsed before then, with the
+/// `Error::Xml` as a warning.
+///
+/// Builders return one from `get_partial`, for callers which would
+/// rather have some data than none. `get` fails instead.
+#[derive(Debug)]
+pub struct Partial<T> {
+    value: T,
+    warnings: Vec<Error>,
+}
+
+impl<T> Partial<T> {
+    /// A response which was parsed to the end.
+    pub fn complete(value: T) -> Self {
+        Partial {
+            value: value,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Partial response from what was parsed and how parsing ended.
+    /// XML errors become warnings, other errors are returned.
+    fn from_result(value: T, res: ::Result<()>) -> ::Result<Self> {
+        match res {
+            Ok(()) => Ok(Partial::complete(value)),
+            Err(err @ Error::Xml { .. }) => {
+                Ok(Partial {
+                    value: value,
+                    warnings: vec![err],
+                })
+            },
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn into_value(self) -> T {
+        self.value
+    }
+
+    pub fn warnings(&self) -> &[Error] {
+        &self.warnings
+    }
+
+    /// Whether the response was parsed to the end, with no warnings
+    pub fn is_complete(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    /// The value if the response is complete, or else the first
+    /// warning as an error.
+    pub fn into_result(mut self) -> ::Result<T> {
+        if self.warnings.is_empty() {
+            Ok(self.value)
+        } else {
+            Err(self.warnings.remove(0))
+        }
+    }
+}
+
+// Display titles
+// ===============================================================
+
+// Shared by the display_title of Agency, Route and Stop
+fn display_title<'a>(title: &'a str, short_title: Option<&'a str>, max_len: usize) -> &'a str {
+    let len = |s: &str| s.chars().count();
+    match short_title {
+        Some(short_title) if len(title) > max_len && len(short_title) < len(title) => short_title,
+        _ => title,
+    }
+}
+
+// Helpers for parsing shared by the API calls
+// ===============================================================
+
+/// Collects the text content of the current element, stopping
+/// at its end tag. Used for elements like `<stop tag="1">Title</stop>`,
+/// where the data is not in the attributes.
+fn parse_text<R: Read>(parser: &mut EventReader<R>) -> ::Result<String> {
+    let mut text = String::new();
+    loop {
+        match parser.next() {
+            Ok(XmlEvent::Characters(chars)) => text.push_str(&chars),
+            Ok(XmlEvent::CData(chars)) => text.push_str(&chars),
+            Ok(XmlEvent::EndElement {..}) => break,
+            Ok(XmlEvent::EndDocument) => break,
+            Err(err) => return Err(err.into()),
+            _ => continue,
+        }
+    }
+    Ok(text)
+}
+
+/// Parses an `<Error shouldRetry="...">` element, which NextBus
+/// sends in place of a response, into an `Error::ApiError`.
+/// Called by every parser when it hits a top-level Error element.
+fn parse_api_error<R: Read>(parser: &mut EventReader<R>,
+                            attributes: Vec<OwnedAttribute>) -> Error {
+    let mut should_retry = false;
+
+    for attribute in attributes {
+        let attribute = attribute.borrow();
+        let name = attribute.name.local_name;
+        let value = attribute.value;
+
+        match name {
+            "shouldRetry" => should_retry = value == "true",
+            _ => (),
+        };
+    }
+
+    // Message is the text of the element, padded with whitespace
+    let message = match parse_text(parser) {
+        Ok(text) => text.trim().to_owned(),
+        Err(err) => return err,
+    };
+
+    Error::ApiError {
+        message: message,
+        should_retry: should_retry,
+    }
+}
+
+/// Fails with the `Error::ApiError` if a response is a NextBus
+/// `<Error>`, reading only up to its first element. For responses
+/// which are passed on rather than parsed, e.g. by `NextBus::send`.
+pub(crate) fn check_api_error<R: Read>(input: R) -> ::Result<()> {
+    let mut parser = EventReader::new(input);
+    loop {
+        match parser.next() {
+            Ok(XmlEvent::StartElement { name, attributes, .. }) => {
+                match name.local_name.as_str() {
+                    "body" => continue,
+                    "Error" => return Err(parse_api_error(&mut parser, attributes)),
+                    _ => return Ok(()),
+                }
+            },
+            Ok(XmlEvent::EndDocument) => return Ok(()),
+            Err(err) => return Err(err.into()),
+            _ => continue,
+        }
+    }
+}
+
+/// Value of a required attribute, or an error naming the element
+/// and attribute it's missing from.
+fn required<T>(value: Option<T>, element: &'static str, attribute: &'static str) -> ::Result<T> {
+    value.ok_or(Error::MissingAttribute {
+        element: element,
+        attribute: attribute,
+    })
+}
+
+/// Parse the value of an attribute, e.g. a lat or epochTime, or an
+/// error with the value which couldn't be parsed.
+fn parse_value<T: FromStr>(value: &str,
+                           element: &'static str,
+                           attribute: &'static str) -> ::Result<T> {
+    value.parse().map_err(|_| Error::InvalidAttribute {
+        element: element,
+        attribute: attribute,
+        value: value.to_owned(),
+    })
+}
+
+// Tests
+// ===============================================================
+
+#[cfg(test)]
+mod test {
+    use error::Error;
+    use std::fmt::Debug;
+    use std::io::Cursor;
+    use super::*;
+    use super::agency_list::AgencyListBuilder;
+    use super::messages::MessagesBuilder;
+    use super::predictions::PredictionsBuilder;
+    use super::predictions_for_multi_stops::PredictionsForMultiStopsBuilder;
+    use super::route_config::RouteConfigBuilder;
+    use super::route_list::RouteListBuilder;
+    use super::schedule::ScheduleBuilder;
+    use super::vehicle_locations::VehicleLocationsBuilder;
+    use xml::reader::{EventReader, XmlEvent};
+
+    const ERROR_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright agencies listed below and NextBus Inc 2016.\">
+        <Error shouldRetry=\"false\">
+            Agency parameter \"a=bad\" is not valid.
+        </Error>
+        </body>";
+
+    const RETRY_ERROR_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright agencies listed below and NextBus Inc 2016.\">
+        <Error shouldRetry=\"true\">
+            Since last time there have been too many requests.
+        </Error>
+        </body>";
+
+    /// Corrupt each value of a good response, and check it fails
+    /// to parse on that element and attribute. Cases are (good value,
+    /// bad value, element, attribute).
+    pub fn assert_invalid_attributes<T, F>(good_xml: &str,
+                                           cases: Vec<(&str, &str, &str, &str)>,
+                                           parse: F)
+        where T: Debug, F: Fn(Cursor<String>) -> ::Result<T>
+    {
+        for (good, bad, element, attribute) in cases {
+            let xml = good_xml.replace(good, bad);
+            assert!(xml != good_xml);
+            match parse(Cursor::new(xml)) {
+                Err(Error::InvalidAttribute { element: e, attribute: a, value }) => {
+                    assert_eq!((e, a), (element, attribute));
+                    assert!(bad.contains(&format!("\"{}\"", value)));
+                },
+                res => panic!("Expected InvalidAttribute for {}, got {:?}", attribute, res),
+            }
+        }
+    }
+
+    #[test]
+    fn picks_display_title() {
+        // (title, short title, max length, display title)
+        let cases = vec![
+            ("84 Mass Ave", Some("84 Mass"), 16, "84 Mass Ave"),
+            ("Kendall to Charles Park", Some("Kendall-Charles"), 16, "Kendall-Charles"),
+            ("Kendall to Charles Park", None, 16, "Kendall to Charles Park"),
+            ("Kendall to Charles Park", Some("Kendall-Charles"), 8, "Kendall-Charles"),
+            ("Tech Shuttle", Some("Tech Shuttle Loop"), 8, "Tech Shuttle"),
+            ("Café Église", Some("Café"), 11, "Café Église"),
+        ];
+        for (title, short_title, max_len, expected) in cases {
+            assert_eq!(display_title(title, short_title, max_len), expected);
+        }
+    }
+
+    #[test]
+    fn every_command_parses_api_error() {
+        let xml = || Cursor::new(ERROR_XML);
+        let results = vec![
+            ("agencyList", AgencyListBuilder::from_xml(xml()).map(|_| ())),
+            ("routeList", RouteListBuilder::from_xml(xml()).map(|_| ())),
+            ("routeConfig", RouteConfigBuilder::from_xml(xml()).map(|_| ())),
+            ("predictions", PredictionsBuilder::from_xml(xml()).map(|_| ())),
+            ("predictionsForMultiStops",
+             PredictionsForMultiStopsBuilder::from_xml(xml()).map(|_| ())),
+            ("schedule", ScheduleBuilder::from_xml(xml()).map(|_| ())),
+            ("messages", MessagesBuilder::from_xml(xml()).map(|_| ())),
+            ("vehicleLocations", VehicleLocationsBuilder::from_xml(xml()).map(|_| ())),
+        ];
+        for (command, res) in results {
+            match res {
+                Err(Error::ApiError { message, should_retry }) => {
+                    assert_eq!(message, "Agency parameter \"a=bad\" is not valid.");
+                    assert!(!should_retry);
+                },
+                res => panic!("Expected ApiError from {}, got {:?}", command, res),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_api_error_should_retry() {
+        let mut parser = EventReader::new(Cursor::new(RETRY_ERROR_XML));
+        loop {
+            match parser.next().unwrap() {
+                XmlEvent::StartElement {ref name, ref attributes, ..}
+                    if name.local_name == "Error" => {
+                    match parse_api_error(&mut parser, attributes.clone()) {
+                        Error::ApiError { message, should_retry } => {
+                            assert_eq!(message,
+                                       "Since last time there have been too many requests.");
+                            assert!(should_retry);
+                        },
+                        err => panic!("Expected ApiError, got {:?}", err),
+                    }
+                    break;
+                },
+                XmlEvent::EndDocument => panic!("No Error element"),
+                _ => continue,
+            }
+        }
+    }
+}
@@ -1,5 +1,7 @@
 //! Next Bus Predictions Command
 
+use NextBus;
+use api::{Partial, Set, Unset};
 use error::Error;
 use request::{Command, Request};
 use std::io::Read;
@@ -9,12 +11,15 @@ use xml::reader::{EventReader, XmlEvent};
 
 /// Predictions for a route. Maps directly from Nextbus response.
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub struct Predictions {
     agency_title: String,
     route_tag: String,
     route_code: Option<String>,
     route_title: String,
     stop_title: String,
+    stop_tag: String,
     dir_title_because_no_predictions: Option<String>,
     directions: Vec<Direction>,
     messages: Vec<Message>,
@@ -26,6 +31,7 @@ impl Predictions {
                route_code: Option<String>,
                route_title: String,
                stop_title: String,
+               stop_tag: String,
                dir_title_because_no_predictions: Option<String>,
                directions: Vec<Direction>,
                messages: Vec<Message>,
@@ -36,134 +42,315 @@ impl Predictions {
             route_code: route_code,
             route_title: route_title,
             stop_title: stop_title,
+            stop_tag: stop_tag,
             dir_title_because_no_predictions: dir_title_because_no_predictions,
             directions: directions,
             messages: messages,
         }
     }
+
+    pub fn agency_title(&self) -> &str {
+        &self.agency_title
+    }
+
+    pub fn route_tag(&self) -> &str {
+        &self.route_tag
+    }
+
+    pub fn route_code(&self) -> Option<&str> {
+        self.route_code.as_ref().map(String::as_str)
+    }
+
+    pub fn route_title(&self) -> &str {
+        &self.route_title
+    }
+
+    pub fn stop_title(&self) -> &str {
+        &self.stop_title
+    }
+
+    pub fn stop_tag(&self) -> &str {
+        &self.stop_tag
+    }
+
+    /// Title of the direction, only sent when there are
+    /// no predictions (so there are no directions)
+    pub fn dir_title_because_no_predictions(&self) -> Option<&str> {
+        self.dir_title_because_no_predictions.as_ref().map(String::as_str)
+    }
+
+    pub fn directions(&self) -> &[Direction] {
+        &self.directions
+    }
+
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
 }
 
 // Builder
 // ===============================================================
 
-pub struct PredictionsBuilder<'a> {
+/// Builder for the predictions of a route at a stop. The route and
+/// stop are required, so `get` is only there once both are set.
+pub struct PredictionsBuilder<'a, R = Unset, S = Unset> {
+    client: NextBus,
     agency: Option<&'a str>,
-    route: Option<&'a str>,
-    stop: Option<&'a str>,
+    route: R,
+    stop: S,
+    use_short_titles: bool,
 }
 
 impl<'a> PredictionsBuilder<'a> {
-    pub fn new() -> Self {
+    pub fn new(client: NextBus) -> Self {
         PredictionsBuilder {
+            client: client,
             agency: None,
-            route: None,
-            stop: None,
+            route: Unset,
+            stop: Unset,
+            use_short_titles: false,
         }
     }
+}
+
+impl<'a, R, S> PredictionsBuilder<'a, R, S> {
+    /// Builder to set the base url for this request only,
+    /// overriding the client's.
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.client.base_url = base_url.to_owned();
+        self
+    }
 
     /// Builder to set agency
-    pub fn agency(&mut self, agency: &'a str) -> &mut Self {
+    pub fn agency(mut self, agency: &'a str) -> Self {
         self.agency = Some(agency);
         self
     }
 
     /// Builder to set route
-    pub fn route(&mut self, route: &'a str) -> &mut Self {
+    pub fn route(self, route: &'a str) -> PredictionsBuilder<'a, Set<&'a str>, S> {
+        PredictionsBuilder {
+            client: self.client,
+            agency: self.agency,
+            route: Set(route),
+            stop: self.stop,
+            use_short_titles: self.use_short_titles,
+        }
+    }
+
+    /// Builder to have NextBus send short titles in place of
+    /// titles, where there are any.
+    pub fn use_short_titles(mut self) -> Self {
+        self.use_short_titles = true;
+        self
+    }
+
+    /// Builder to set stop
+    pub fn stop(self, stop: &'a str) -> PredictionsBuilder<'a, R, Set<&'a str>> {
+        PredictionsBuilder {
+            client: self.client,
+            agency: self.agency,
+            route: self.route,
+            stop: Set(stop),
+            use_short_titles: self.use_short_titles,
+        }
+    }
+}
+
+impl<'a> PredictionsBuilder<'a, Set<&'a str>, Set<&'a str>> {
+    pub fn get(&self) -> ::Result<Predictions> {
+        self.get_partial().and_then(Partial::into_result)
+    }
+
+    /// Like get, but if the response breaks off (e.g. the body is
+    /// cut short), returns the directions parsed before then, with a
+    /// warning.
+    pub fn get_partial(&self) -> ::Result<Partial<Predictions>> {
+        // Check if agency is none. If so, send error.
+        let agency = try!(self.client.agency_or_default(self.agency));
+
+        // Request, and parse xml into predictions struct
+        let mut request = Request::new();
+        request.command(Command::Predictions).agency(agency).route(self.route.0).stop(self.stop.0);
+        if self.use_short_titles {
+            request.use_short_titles();
+        }
+        self.client.get(&request, PredictionsBuilder::from_xml_partial)
+    }
+}
+
+impl<'a> PredictionsBuilder<'a> {
+    #[cfg(test)]
+    pub(super) fn from_xml<R: Read>(input: R) -> ::Result<Predictions> {
+        Self::from_xml_partial(input).and_then(Partial::into_result)
+    }
+
+    fn from_xml_partial<R: Read>(input: R) -> ::Result<Partial<Predictions>> {
+        // Response for one route and stop has one predictions element,
+        // which a partial response may have broken off before
+        let Partial { value, mut warnings } = try!(parse_predictions_list_partial(input));
+        match value.into_iter().next() {
+            Some(predictions) => {
+                Ok(Partial {
+                    value: predictions,
+                    warnings: warnings,
+                })
+            },
+            None if !warnings.is_empty() => Err(warnings.remove(0)),
+            None => Err(Error::MissingElement("predictions")),
+        }
+    }
+}
+
+// Builder by stop id
+// ===============================================================
+
+/// Builder for the predictions at a stop, by its agency-wide stop id
+/// (e.g. the number on the stop's sign), with one Predictions for
+/// each route serving it. The stop id is required, so `get` is only
+/// there once it's set.
+pub struct PredictionsByStopIdBuilder<'a, S = Unset> {
+    client: NextBus,
+    agency: Option<&'a str>,
+    route: Option<&'a str>,
+    stop_id: S,
+    use_short_titles: bool,
+}
+
+impl<'a> PredictionsByStopIdBuilder<'a> {
+    pub fn new(client: NextBus) -> Self {
+        PredictionsByStopIdBuilder {
+            client: client,
+            agency: None,
+            route: None,
+            stop_id: Unset,
+            use_short_titles: false,
+        }
+    }
+}
+
+impl<'a, S> PredictionsByStopIdBuilder<'a, S> {
+    /// Builder to set the base url for this request only,
+    /// overriding the client's.
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.client.base_url = base_url.to_owned();
+        self
+    }
+
+    /// Builder to set agency
+    pub fn agency(mut self, agency: &'a str) -> Self {
+        self.agency = Some(agency);
+        self
+    }
+
+    /// Builder to set route. If not set, gets predictions for
+    /// every route serving the stop.
+    pub fn route(mut self, route: &'a str) -> Self {
         self.route = Some(route);
         self
     }
 
-    /// Builder to set route
-    pub fn stop(&mut self, stop: &'a str) -> &mut Self {
-        self.stop = Some(stop);
+    /// Builder to have NextBus send short titles in place of
+    /// titles, where there are any.
+    pub fn use_short_titles(mut self) -> Self {
+        self.use_short_titles = true;
         self
     }
 
-    pub fn get(&self) -> ::Result<Predictions> {
-        // Check if agency or route is none. If so, send error.
-        let agency = try!(self.agency.ok_or(Error::BuildCommandError));
-        let route = try!(self.route.ok_or(Error::BuildCommandError));
-        let stop = try!(self.stop.ok_or(Error::BuildCommandError));
-
-        // Request and get back
-        let res = try!(Request::new()
-            .command(Command::Predictions)
-            .agency(agency)
-            .route(route)
-            .stop(stop)
-            .send());
-
-        // Parse xml into route list struct
-        Self::from_xml(res)
-    }
-
-    fn from_xml<R: Read>(input: R) -> ::Result<Predictions> {
-        // initializing vars for Predictions attributes
-        let mut agency_title = None ;
-        let mut route_tag = None ;
-        let mut route_code = None ;
-        let mut route_title = None ;
-        let mut stop_title = None ;
-        let mut dir_title_because_no_predictions = None ;
-
-        // Vec for collecting direction and messages
-        let mut directions = vec![];
-        let mut messages = vec![];
-
-        let mut parser = EventReader::new(input);
-
-        loop {
-            match parser.next() {
-                Ok(XmlEvent::StartElement {name, attributes, ..}) => {
-                    let name = name.borrow().local_name;
-
-                    if name == "body" { continue };
-
-                    if name == "predictions" {
-
-                        for attribute in attributes {
-                            let attribute = attribute.borrow();
-                            let name = attribute.name.local_name;
-                            let value = attribute.value;
-
-                            match name {
-                                "agencyTitle" => agency_title = Some(value.to_owned()),
-                                "routeTag" => route_tag = Some(value.to_owned()),
-                                "routeCode" => route_code = Some(value.to_owned()),
-                                "routeTitle" => route_title = Some(value.to_owned()),
-                                "stopTitle" => stop_title = Some(value.to_owned()),
-                                "dirTitleBecauseNoPredictions" =>
-                                    dir_title_because_no_predictions = Some(value.to_owned()),
-                                _ => (),
-                            };
-                        }
-                    } else if name == "direction" {
-                        try!(add_direction_to_directions(&mut parser,
-                                                         attributes,
-                                                         &mut directions,
-                                                         ));
-                    } else if name == "message" {
-                        try!(add_message_to_messages(attributes,
-                                                     &mut messages
-                                                     ));
-                    }
-                },
-                Ok(XmlEvent::EndDocument) => break,
-                Ok(_) => continue,
-                Err(_) => break, // Later cover Err
-            }
+    /// Builder to set stop id
+    pub fn stop_id(self, stop_id: &'a str) -> PredictionsByStopIdBuilder<'a, Set<&'a str>> {
+        PredictionsByStopIdBuilder {
+            client: self.client,
+            agency: self.agency,
+            route: self.route,
+            stop_id: Set(stop_id),
+            use_short_titles: self.use_short_titles,
         }
-        Ok(Predictions {
-            agency_title: try!(agency_title.ok_or(Error::ParseError)),
-            route_tag: try!(route_tag.ok_or(Error::ParseError)),
-            route_code: route_code,
-            route_title: try!(route_title.ok_or(Error::ParseError)),
-            stop_title: try!(stop_title.ok_or(Error::ParseError)),
-            dir_title_because_no_predictions:
-                dir_title_because_no_predictions,
-            directions: directions,
-            messages: messages,
-        })
+    }
+}
+
+impl<'a> PredictionsByStopIdBuilder<'a, Set<&'a str>> {
+    pub fn get(&self) -> ::Result<Vec<Predictions>> {
+        self.get_partial().and_then(Partial::into_result)
+    }
+
+    /// Like get, but if the response breaks off (e.g. the body is
+    /// cut short), returns the predictions parsed before then, with
+    /// a warning.
+    pub fn get_partial(&self) -> ::Result<Partial<Vec<Predictions>>> {
+        // Check if agency is none. If so, send error.
+        let agency = try!(self.client.agency_or_default(self.agency));
+
+        // Request. Allow one route or no route param (returns all routes)
+        let mut request = Request::new();
+        request.command(Command::Predictions).agency(agency).stop_id(self.stop_id.0);
+        if let Some(route) = self.route {
+            request.route(route);
+        }
+        if self.use_short_titles {
+            request.use_short_titles();
+        }
+
+        // Parse xml into list of predictions, one per route
+        self.client.get(&request, parse_predictions_list_partial)
+    }
+}
+
+// Owned request
+// ===============================================================
+
+/// A predictions request which owns its parameters, so it can be
+/// kept (e.g. built from config), cloned, sent across threads and
+/// run repeatedly with a client. The route and stop are required,
+/// so they're given up front.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PredictionsRequest {
+    agency: Option<String>,
+    route: String,
+    stop: String,
+    use_short_titles: bool,
+}
+
+impl PredictionsRequest {
+    pub fn new(route: &str, stop: &str) -> Self {
+        PredictionsRequest {
+            agency: None,
+            route: route.to_owned(),
+            stop: stop.to_owned(),
+            use_short_titles: false,
+        }
+    }
+
+    /// Set agency. If not set, uses the client's.
+    pub fn agency(&mut self, agency: &str) -> &mut Self {
+        self.agency = Some(agency.to_owned());
+        self
+    }
+
+    /// Have NextBus send short titles in place of titles
+    pub fn use_short_titles(&mut self) -> &mut Self {
+        self.use_short_titles = true;
+        self
+    }
+
+    pub fn get(&self, client: &NextBus) -> ::Result<Predictions> {
+        self.builder(client).get()
+    }
+
+    pub fn get_partial(&self, client: &NextBus) -> ::Result<Partial<Predictions>> {
+        self.builder(client).get_partial()
+    }
+
+    fn builder<'a>(&'a self, client: &NextBus)
+                   -> PredictionsBuilder<'a, Set<&'a str>, Set<&'a str>> {
+        let mut builder = client.predictions().route(&self.route).stop(&self.stop);
+        if let Some(ref agency) = self.agency {
+            builder = builder.agency(agency);
+        }
+        if self.use_short_titles {
+            builder = builder.use_short_titles();
+        }
+        builder
     }
 }
 
@@ -173,6 +360,8 @@ impl<'a> PredictionsBuilder<'a> {
 /// Used for parsing RouteList Nextbus response.
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub struct Direction {
     title: String,
     predictions: Vec<Prediction>,
@@ -185,9 +374,19 @@ impl Direction {
             predictions: predictions,
         }
     }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn predictions(&self) -> &[Prediction] {
+        &self.predictions
+    }
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub struct Prediction {
     seconds: usize,
     minutes: usize,
@@ -228,9 +427,61 @@ impl Prediction {
             delayed: delayed,
         }
     }
+
+    pub fn seconds(&self) -> usize {
+        self.seconds
+    }
+
+    pub fn minutes(&self) -> usize {
+        self.minutes
+    }
+
+    /// Predicted arrival (or departure), in msec since the epoch
+    pub fn epoch_time(&self) -> usize {
+        self.epoch_time
+    }
+
+    /// True if the prediction is for leaving the stop,
+    /// which is the case at the start of a route
+    pub fn is_departure(&self) -> bool {
+        self.is_departure
+    }
+
+    pub fn block(&self) -> &str {
+        &self.block
+    }
+
+    pub fn dir_tag(&self) -> &str {
+        &self.dir_tag
+    }
+
+    pub fn trip_tag(&self) -> Option<&str> {
+        self.trip_tag.as_ref().map(String::as_str)
+    }
+
+    /// Only for Toronto TTC agency
+    pub fn branch(&self) -> Option<&str> {
+        self.branch.as_ref().map(String::as_str)
+    }
+
+    pub fn affected_by_layover(&self) -> Option<bool> {
+        self.affected_by_layover
+    }
+
+    /// Only exists when true
+    pub fn is_schedule_based(&self) -> Option<bool> {
+        self.is_schedule_based
+    }
+
+    /// Only for certain agencies
+    pub fn delayed(&self) -> Option<bool> {
+        self.delayed
+    }
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub struct Message {
     text: String,
     priority: Option<String>,
@@ -243,12 +494,142 @@ impl Message {
             priority: priority
         }
     }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn priority(&self) -> Option<&str> {
+        self.priority.as_ref().map(String::as_str)
+    }
 }
 
 // ===============================================================
 // Helpers for parsing
 // ===============================================================
 
+// Parsing a list of Predictions
+//
+// Shared with predictionsForMultiStops, which returns one
+// predictions element per route and stop.
+// ===============================================================
+
+pub fn parse_predictions_list<R: Read>(input: R) -> ::Result<Vec<Predictions>> {
+    parse_predictions_list_partial(input).and_then(Partial::into_result)
+}
+
+/// Like parse_predictions_list, but if the XML breaks off, returns
+/// the predictions parsed before then, with a warning.
+pub fn parse_predictions_list_partial<R: Read>(input: R)
+                                               -> ::Result<Partial<Vec<Predictions>>> {
+    // Vec for collecting predictions
+    let mut predictions_list = vec![];
+    let res = collect_predictions_list(input, &mut predictions_list);
+    Partial::from_result(predictions_list, res)
+}
+
+fn collect_predictions_list<R: Read>(input: R,
+                                     predictions_list: &mut Vec<Predictions>) -> ::Result<()> {
+    let mut parser = EventReader::new(input);
+
+    loop {
+        match parser.next() {
+            Ok(XmlEvent::StartElement {name, attributes, ..}) => {
+                if name.borrow().local_name == "Error" {
+                    return Err(super::parse_api_error(&mut parser, attributes));
+                }
+
+                if name.borrow().local_name == "predictions" {
+                    try!(add_predictions_to_predictions_list(&mut parser,
+                                                             attributes,
+                                                             predictions_list));
+                }
+            },
+            Ok(XmlEvent::EndDocument) => break,
+            Ok(_) => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(())
+}
+
+fn add_predictions_to_predictions_list<R: Read>(mut parser: &mut EventReader<R>,
+                                                attributes: Vec<OwnedAttribute>,
+                                                predictions_list: &mut Vec<Predictions>)
+                                                -> ::Result<()> {
+    // initializing vars for Predictions attributes
+    let mut agency_title = None ;
+    let mut route_tag = None ;
+    let mut route_code = None ;
+    let mut route_title = None ;
+    let mut stop_title = None ;
+    let mut stop_tag = None ;
+    let mut dir_title_because_no_predictions = None ;
+
+    for attribute in attributes {
+        let attribute = attribute.borrow();
+        let name = attribute.name.local_name;
+        let value = attribute.value;
+
+        match name {
+            "agencyTitle" => agency_title = Some(value.to_owned()),
+            "routeTag" => route_tag = Some(value.to_owned()),
+            "routeCode" => route_code = Some(value.to_owned()),
+            "routeTitle" => route_title = Some(value.to_owned()),
+            "stopTitle" => stop_title = Some(value.to_owned()),
+            "stopTag" => stop_tag = Some(value.to_owned()),
+            "dirTitleBecauseNoPredictions" =>
+                dir_title_because_no_predictions = Some(value.to_owned()),
+            _ => (),
+        };
+    }
+
+    // Vec for collecting direction and messages
+    let mut directions = vec![];
+    let mut messages = vec![];
+
+    loop {
+        match parser.next() {
+            Ok(XmlEvent::StartElement {name, attributes, ..}) => {
+                let name = name.borrow().local_name;
+
+                if name == "direction" {
+                    try!(add_direction_to_directions(&mut parser,
+                                                     attributes,
+                                                     &mut directions,
+                                                     ));
+                } else if name == "message" {
+                    try!(add_message_to_messages(attributes,
+                                                 &mut messages
+                                                 ));
+                }
+            },
+            Ok(XmlEvent::EndElement {name, ..}) => {
+                if name.borrow().local_name == "predictions" {
+                    break;
+                }
+            },
+            Err(err) => return Err(err.into()),
+            _ => continue,
+        }
+    }
+
+    predictions_list.push(Predictions {
+        agency_title: try!(super::required(agency_title, "predictions", "agencyTitle")),
+        route_tag: try!(super::required(route_tag, "predictions", "routeTag")),
+        route_code: route_code,
+        route_title: try!(super::required(route_title, "predictions", "routeTitle")),
+        stop_title: try!(super::required(stop_title, "predictions", "stopTitle")),
+        stop_tag: try!(super::required(stop_tag, "predictions", "stopTag")),
+        dir_title_because_no_predictions:
+            dir_title_because_no_predictions,
+        directions: directions,
+        messages: messages,
+    });
+    Ok(())
+}
+
 // Adding a direction
 // ===============================================================
 
@@ -274,7 +655,7 @@ fn add_direction_to_directions<R: Read>(mut parser: &mut EventReader<R>,
 
     // Set Direction
     directions.push(Direction {
-        title: try!(title.ok_or(Error::ParseError)),
+        title: try!(super::required(title, "direction", "title")),
         predictions: predictions,
     });
 
@@ -299,7 +680,7 @@ fn parse_prediction_elements<R: Read>(mut parser: &mut EventReader<R>,
                     break;
                 }
             },
-            Err(_) => break,
+            Err(err) => return Err(err.into()),
             _ => continue,
         }
     }
@@ -326,27 +707,32 @@ fn add_prediction_to_predictions(attributes: Vec<OwnedAttribute>,
         let value = attribute.value;
 
         match name {
-            "seconds" => seconds = Some(value.parse().unwrap()),
-            "minutes" => minutes = Some(value.parse().unwrap()),
-            "epochTime" => epoch_time = Some(value.parse().unwrap()),
-            "isDeparture" => is_departure = Some(value.parse().unwrap()),
+            "seconds" => seconds = Some(try!(super::parse_value(value, "prediction", "seconds"))),
+            "minutes" => minutes = Some(try!(super::parse_value(value, "prediction", "minutes"))),
+            "epochTime" =>
+                epoch_time = Some(try!(super::parse_value(value, "prediction", "epochTime"))),
+            "isDeparture" =>
+                is_departure = Some(try!(super::parse_value(value, "prediction", "isDeparture"))),
             "block" => block = Some(value.to_owned()),
             "dirTag" => dir_tag = Some(value.to_owned()),
             "tripTag" => trip_tag = Some(value.to_owned()),
             "branch" => branch = Some(value.to_owned()),
-            "affecteByLayover" => affected_by_layover = Some(value.parse().unwrap()),
-            "isScheduleBased" => is_schedule_based = Some(value.parse().unwrap()),
-            "delayed" => delayed = Some(value.parse().unwrap()),
+            "affectedByLayover" =>
+                affected_by_layover =
+                    Some(try!(super::parse_value(value, "prediction", "affectedByLayover"))),
+            "isScheduleBased" =>
+                is_schedule_based = Some(try!(super::parse_value(value, "prediction", "isScheduleBased"))),
+            "delayed" => delayed = Some(try!(super::parse_value(value, "prediction", "delayed"))),
             _ => (),
         }
     }
     predictions.push(Prediction {
-        seconds: try!(seconds.ok_or(Error::ParseError)),
-        minutes: try!(minutes.ok_or(Error::ParseError)),
-        epoch_time: try!(epoch_time.ok_or(Error::ParseError)),
-        is_departure: try!(is_departure.ok_or(Error::ParseError)),
-        block: try!(block.ok_or(Error::ParseError)),
-        dir_tag: try!(dir_tag.ok_or(Error::ParseError)),
+        seconds: try!(super::required(seconds, "prediction", "seconds")),
+        minutes: try!(super::required(minutes, "prediction", "minutes")),
+        epoch_time: try!(super::required(epoch_time, "prediction", "epochTime")),
+        is_departure: try!(super::required(is_departure, "prediction", "isDeparture")),
+        block: try!(super::required(block, "prediction", "block")),
+        dir_tag: try!(super::required(dir_tag, "prediction", "dirTag")),
         trip_tag: trip_tag,
         branch: branch,
         affected_by_layover: affected_by_layover,
@@ -372,7 +758,7 @@ fn add_message_to_messages(attributes: Vec<OwnedAttribute>,
         }
     }
     messages.push(Message {
-        text: try!(text.ok_or(Error::ParseError)),
+        text: try!(super::required(text, "message", "text")),
         priority: priority,
     });
     Ok(())
@@ -383,9 +769,27 @@ fn add_message_to_messages(attributes: Vec<OwnedAttribute>,
 
 #[cfg(test)]
 mod test {
+    use api::test::assert_invalid_attributes;
+    use error::Error;
     use std::io::Cursor;
+    use NextBus;
+    use transport::fixture_client;
     use super::*;
 
+    const GOOD_PREDICTIONS_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright Massachusetts Institute of Technology 2016.\">
+        <predictions agencyTitle=\"Massachusetts Institute of Technology\"
+            routeTitle=\"Saferide Campus Shuttle\" routeTag=\"saferidecampshut\"
+            stopTitle=\"84 Mass Ave\" stopTag=\"mass84_d\">
+        <direction title=\"Loop\">
+        <prediction epochTime=\"1465430935426\" seconds=\"248\" minutes=\"4\"
+            isDeparture=\"false\" affectedByLayover=\"true\" isScheduleBased=\"true\"
+            delayed=\"false\" dirTag=\"loop\" vehicle=\"1\" block=\"campshut\" />
+        </direction>
+        </predictions>
+        </body>";
+
 //    #[test]
 //    fn parse_good_xml() {
 //        let buffer = Cursor::new(GOOD_ROUTE_XML);
@@ -431,16 +835,86 @@ mod test {
 //    }
 
     #[test]
-//    #[ignore]
+    fn parse_good_xml() {
+        let predictions = PredictionsBuilder::from_xml(Cursor::new(GOOD_PREDICTIONS_XML)).unwrap();
+        let prediction = &predictions.directions()[0].predictions()[0];
+        assert_eq!(prediction.minutes(), 4);
+        assert_eq!(prediction.affected_by_layover(), Some(true));
+        assert_eq!(prediction.is_schedule_based(), Some(true));
+        assert_eq!(prediction.delayed(), Some(false));
+    }
+
+    #[test]
+    fn parse_invalid_attribute() {
+        // Corrupted value in the response, and where it should fail
+        let cases = vec![
+            ("affectedByLayover=\"true\"", "affectedByLayover=\"yes\"",
+             "prediction", "affectedByLayover"),
+            ("isScheduleBased=\"true\"", "isScheduleBased=\"1\"",
+             "prediction", "isScheduleBased"),
+            ("delayed=\"false\"", "delayed=\"\"", "prediction", "delayed"),
+        ];
+        assert_invalid_attributes(GOOD_PREDICTIONS_XML, cases, PredictionsBuilder::from_xml);
+    }
+
+    #[test]
+    fn get_missing_agency() {
+        // Route and stop are checked when compiling, the agency
+        // only once there's no client agency to fall back on.
+        let builder = PredictionsBuilder::new(NextBus::new())
+            .route("saferidecampshut")
+            .stop("mass84_d");
+        match builder.get() {
+            Err(Error::MissingParameter(param)) => assert_eq!(param, "agency"),
+            res => panic!("Expected MissingParameter, got {:?}", res),
+        }
+    }
+
+    #[test]
     fn should_get_predictions() {
-        let predictions = PredictionsBuilder::new()
+        let predictions = PredictionsBuilder::new(fixture_client())
             .agency("mit")
             .route("saferidecampshut")
             .stop("mass84_d")
             .get()
             .unwrap();
-        println!("{:?}", predictions);
-        assert!(false);
+        assert_eq!(predictions.stop_tag(), "mass84_d");
+        let minutes: Vec<_> = predictions.directions()[0]
+            .predictions()
+            .iter()
+            .map(|prediction| prediction.minutes())
+            .collect();
+        assert_eq!(minutes, vec![4, 19]);
     }
-}
 
+    #[test]
+    fn should_get_predictions_repeatedly() {
+        let mut request = PredictionsRequest::new("saferidecampshut", "mass84_d");
+        request.agency("mit");
+        let client = fixture_client();
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let request = request.clone();
+                let client = client.clone();
+                ::std::thread::spawn(move || request.get(&client).unwrap())
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap().stop_tag(), "mass84_d");
+        }
+    }
+
+    #[test]
+    fn should_get_predictions_by_stop_id() {
+        let predictions = PredictionsByStopIdBuilder::new(fixture_client())
+            .agency("sf-muni")
+            .stop_id("14448")
+            .get()
+            .unwrap();
+        let routes: Vec<_> = predictions.iter().map(Predictions::route_tag).collect();
+        assert_eq!(routes, vec!["J", "N"]);
+        assert_eq!(predictions[0].directions()[0].predictions().len(), 2);
+        assert_eq!(predictions[1].dir_title_because_no_predictions(),
+                   Some("Inbound to Caltrain via Downtown"));
+    }
+}
@@ -0,0 +1,222 @@
+//! Next Bus Predictions For Multi Stops Command
+
+use api::{Partial, Set, Unset};
+use api::predictions::{self, Predictions};
+use NextBus;
+use request::{Command, Request};
+use std::io::Read;
+
+// Builder
+// ===============================================================
+
+/// Builder for the predictions at several stops in one request. At
+/// least one stop is required, so `get` is only there once the first
+/// is added.
+pub struct PredictionsForMultiStopsBuilder<'a, S = Unset> {
+    client: NextBus,
+    agency: Option<&'a str>,
+    first_stop: S,
+    stops: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> PredictionsForMultiStopsBuilder<'a> {
+    pub fn new(client: NextBus) -> Self {
+        PredictionsForMultiStopsBuilder {
+            client: client,
+            agency: None,
+            first_stop: Unset,
+            stops: Vec::new(),
+        }
+    }
+
+    /// Builder to add the first route and stop.
+    pub fn stop(self, route: &'a str, stop: &'a str)
+                -> PredictionsForMultiStopsBuilder<'a, Set<(&'a str, &'a str)>> {
+        PredictionsForMultiStopsBuilder {
+            client: self.client,
+            agency: self.agency,
+            first_stop: Set((route, stop)),
+            stops: self.stops,
+        }
+    }
+}
+
+impl<'a, S> PredictionsForMultiStopsBuilder<'a, S> {
+    /// Builder to set the base url for this request only,
+    /// overriding the client's.
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.client.base_url = base_url.to_owned();
+        self
+    }
+
+    /// Builder to set agency
+    pub fn agency(mut self, agency: &'a str) -> Self {
+        self.agency = Some(agency);
+        self
+    }
+}
+
+impl<'a> PredictionsForMultiStopsBuilder<'a, Set<(&'a str, &'a str)>> {
+    /// Builder to add another route and stop. Can be called multiple
+    /// times to get predictions for many stops in one request.
+    pub fn stop(mut self, route: &'a str, stop: &'a str) -> Self {
+        self.stops.push((route, stop));
+        self
+    }
+
+    /// Builder to add a list of routes and stops after the first.
+    pub fn stops(mut self, stops: Vec<(&'a str, &'a str)>) -> Self {
+        self.stops.extend(stops);
+        self
+    }
+
+    pub fn get(&self) -> ::Result<Vec<Predictions>> {
+        self.get_partial().and_then(Partial::into_result)
+    }
+
+    /// Like get, but if the response breaks off (e.g. the body is
+    /// cut short), returns the predictions parsed before then, with
+    /// a warning.
+    pub fn get_partial(&self) -> ::Result<Partial<Vec<Predictions>>> {
+        // Check if agency is none. If so, send error.
+        let agency = try!(self.client.agency_or_default(self.agency));
+
+        // Nextbus takes each stop as "route|stop"
+        let stops: Vec<_> = Some(&self.first_stop.0).into_iter()
+            .chain(self.stops.iter())
+            .map(|&(route, stop)| format!("{}|{}", route, stop))
+            .collect();
+
+        // Request, and parse xml into list of predictions
+        self.client.get(Request::new()
+            .command(Command::PredictionsForMultiStops)
+            .agency(agency)
+            .stops(stops.iter().map(|stop| &stop[..]).collect()),
+            PredictionsForMultiStopsBuilder::from_xml_partial)
+    }
+}
+
+impl<'a> PredictionsForMultiStopsBuilder<'a> {
+    #[cfg(test)]
+    pub(super) fn from_xml<R: Read>(input: R) -> ::Result<Vec<Predictions>> {
+        predictions::parse_predictions_list(input)
+    }
+
+    fn from_xml_partial<R: Read>(input: R) -> ::Result<Partial<Vec<Predictions>>> {
+        predictions::parse_predictions_list_partial(input)
+    }
+}
+
+// Tests
+// ===============================================================
+
+#[cfg(test)]
+mod test {
+    use api::test::assert_invalid_attributes;
+    use api::predictions::{Direction, Message, Prediction, Predictions};
+    use std::io::Cursor;
+    use transport::fixture_client;
+    use super::*;
+
+    const GOOD_MULTI_STOPS_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright MBTA 2016.\">
+        <predictions agencyTitle=\"MBTA\" routeTag=\"1\" routeTitle=\"1\"
+            stopTitle=\"Massachusetts Ave @ Holyoke St\" stopTag=\"110\">
+        <direction title=\"Dudley Station via Mass. Ave.\">
+        <prediction epochTime=\"1465430935426\" seconds=\"248\" minutes=\"4\"
+            isDeparture=\"false\" dirTag=\"1_1_var0\" vehicle=\"0634\" block=\"C01_5\"
+            tripTag=\"30280826\"/>
+        </direction>
+        <message text=\"Detour in effect\" priority=\"Normal\"/>
+        </predictions>
+        <predictions agencyTitle=\"MBTA\" routeTag=\"47\" routeTitle=\"47\"
+            stopTitle=\"Central Square\" stopTag=\"1123\"
+            dirTitleBecauseNoPredictions=\"Broadway Station\">
+        </predictions>
+        </body>";
+
+    #[test]
+    fn parse_good_xml() {
+        let buffer = Cursor::new(GOOD_MULTI_STOPS_XML);
+        let predictions = PredictionsForMultiStopsBuilder::from_xml(buffer).unwrap();
+
+        let test_1 = Predictions::new(
+            "MBTA".to_owned(),
+            "1".to_owned(),
+            None,
+            "1".to_owned(),
+            "Massachusetts Ave @ Holyoke St".to_owned(),
+            "110".to_owned(),
+            None,
+            vec![Direction::new("Dudley Station via Mass. Ave.".to_owned(), vec![
+                Prediction::new(248,
+                                4,
+                                1465430935426,
+                                false,
+                                "C01_5".to_owned(),
+                                "1_1_var0".to_owned(),
+                                Some("30280826".to_owned()),
+                                None,
+                                None,
+                                None,
+                                None),
+            ])],
+            vec![Message::new("Detour in effect".to_owned(), Some("Normal".to_owned()))]);
+        let test_47 = Predictions::new(
+            "MBTA".to_owned(),
+            "47".to_owned(),
+            None,
+            "47".to_owned(),
+            "Central Square".to_owned(),
+            "1123".to_owned(),
+            Some("Broadway Station".to_owned()),
+            vec![],
+            vec![]);
+
+        assert_eq!(predictions, vec![test_1, test_47]);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_round_trip() {
+        use serde_json;
+
+        let buffer = Cursor::new(GOOD_MULTI_STOPS_XML);
+        let predictions = PredictionsForMultiStopsBuilder::from_xml(buffer).unwrap();
+
+        // Field names match the Nextbus attributes
+        let json = serde_json::to_string(&predictions).unwrap();
+        assert!(json.contains("\"stopTag\":\"110\""));
+        assert!(json.contains("\"epochTime\":1465430935426"));
+        assert!(json.contains("\"dirTitleBecauseNoPredictions\":\"Broadway Station\""));
+
+        let from_json: Vec<Predictions> = serde_json::from_str(&json).unwrap();
+        assert_eq!(from_json, predictions);
+    }
+
+    #[test]
+    fn parse_invalid_attribute() {
+        // Corrupted value in the response, and where it should fail
+        let cases = vec![
+            ("epochTime=\"1465430935426\"", "epochTime=\"1465430935.426\"", "prediction", "epochTime"),
+            ("seconds=\"248\"", "seconds=\"-248\"", "prediction", "seconds"),
+            ("minutes=\"4\"", "minutes=\"four\"", "prediction", "minutes"),
+            ("isDeparture=\"false\"", "isDeparture=\"False\"", "prediction", "isDeparture"),
+        ];
+        assert_invalid_attributes(GOOD_MULTI_STOPS_XML, cases,
+                                  PredictionsForMultiStopsBuilder::from_xml);
+    }
+
+    #[test]
+    fn should_get_predictions_for_multi_stops() {
+        let predictions = PredictionsForMultiStopsBuilder::new(fixture_client())
+            .agency("mbta")
+            .stop("1", "110")
+            .stop("47", "1123")
+            .get()
+            .unwrap();
+        let stops: Vec<_> = predictions.iter().map(|prediction| prediction.stop_tag()).collect();
+        assert_eq!(stops, vec!["110", "1123"]);
+    }
+}
@@ -1,15 +1,15 @@
 //! Next Bus Route List Command
 //!
 
-//TODO: add "terse" option for no path
-
-use error::Error;
+use NextBus;
+use api::Partial;
 use request::{Command, Request};
 use std::io::Read;
 use xml::reader::{EventReader, XmlEvent};
 use xml::attribute::OwnedAttribute;
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RouteConfig(Vec<Route>);
 
 impl RouteConfig {
@@ -41,86 +41,183 @@ impl<'a> IntoIterator for &'a RouteConfig {
 // ===============================================================
 
 pub struct RouteConfigBuilder<'a> {
+    client: NextBus,
     agency: Option<&'a str>,
     route: Option<&'a str>,
     terse: bool,
+    use_short_titles: bool,
 }
 
 impl<'a> RouteConfigBuilder<'a> {
-    pub fn new() -> Self {
+    pub fn new(client: NextBus) -> Self {
         RouteConfigBuilder {
+            client: client,
             agency: None,
             route: None,
             terse: false,
+            use_short_titles: false,
         }
     }
 
+    /// Builder to set the base url for this request only,
+    /// overriding the client's.
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.client.base_url = base_url.to_owned();
+        self
+    }
+
     /// Builder to set agency
-    pub fn agency(&mut self, agency: &'a str) -> &mut Self {
+    pub fn agency(mut self, agency: &'a str) -> Self {
         self.agency = Some(agency);
         self
     }
 
     /// Builder to set route
-    pub fn route(&mut self, route: &'a str) -> &mut Self {
+    pub fn route(mut self, route: &'a str) -> Self {
         self.route = Some(route);
         self
     }
 
-    /// Builder to set route
-    pub fn terse(&mut self) -> &mut Self {
+    /// Builder to leave out paths
+    pub fn terse(mut self) -> Self {
         self.terse = true;
         self
     }
 
+    /// Builder to have NextBus send short titles in place of
+    /// titles, where there are any.
+    pub fn use_short_titles(mut self) -> Self {
+        self.use_short_titles = true;
+        self
+    }
+
     pub fn get(&self) -> ::Result<RouteConfig> {
         // Check if agency or route is none. If so, send error.
-        let agency = try!(self.agency.ok_or(Error::BuildCommandError));
+        let agency = try!(self.client.agency_or_default(self.agency));
+        let res = self.get_partial().and_then(Partial::into_result);
+
+        // When NextBus can't be reached, or the response is cut
+        // short, fall back on a snapshot
+        match res {
+            Err(ref err) if err.should_retry() => {
+                if let Some(route_config) = self.offline(agency) {
+                    return Ok(route_config);
+                }
+            },
+            _ => (),
+        }
+        res
+    }
+
+    /// Like get, but if the response breaks off (e.g. the body is
+    /// cut short), returns the routes parsed before then, with a
+    /// warning. Doesn't fall back on the client's store.
+    pub fn get_partial(&self) -> ::Result<Partial<RouteConfig>> {
+        let agency = try!(self.client.agency_or_default(self.agency));
 
         // Request. Allow one route or no route param (returns all routes)
-        // TODO: add the terse option here
-        let res = match self.route {
-            Some(route) => {
-                try!(Request::new()
-                    .command(Command::RouteConfig)
-                    .agency(agency)
-                    .route(route)
-                    .send())
-            },
-            None => {
-                try!(Request::new()
-                    .command(Command::RouteConfig)
-                    .agency(agency)
-                    .send())
-            }
+        let mut request = Request::new();
+        request.command(Command::RouteConfig).agency(agency);
+        if let Some(route) = self.route {
+            request.route(route);
+        }
+        if self.terse {
+            request.terse();
+        }
+        if self.use_short_titles {
+            request.use_short_titles();
+        }
+        self.client.get(&request, parse_route_config_partial)
+    }
+
+    // Route config from the client's store, filtered by route
+    fn offline(&self, agency: &str) -> Option<RouteConfig> {
+        let store = match self.client.route_config_store() {
+            Some(store) => store,
+            None => return None,
         };
+        let routes = match store.load(agency) {
+            Ok(snapshot) => snapshot.into_route_config(),
+            Err(_) => return None,
+        };
+        Some(match self.route {
+            Some(tag) => RouteConfig(routes.into_iter().filter(|route| route.tag == tag).collect()),
+            None => routes,
+        })
+    }
 
-        Self::from_xml(res)
+    #[cfg(test)]
+    pub(super) fn from_xml<R: Read>(input: R) -> ::Result<RouteConfig> {
+        parse_route_config(input)
     }
+}
 
-    fn from_xml<R: Read>(input: R) -> ::Result<RouteConfig> {
-        // Vec for collecting routes
-        let mut routes = vec![];
+// Owned request
+// ===============================================================
 
-        let mut parser = EventReader::new(input);
+/// A route config request which owns its parameters, so it can be
+/// kept (e.g. built from config), cloned, sent across threads and
+/// run repeatedly with a client.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RouteConfigRequest {
+    agency: Option<String>,
+    route: Option<String>,
+    terse: bool,
+    use_short_titles: bool,
+}
 
-        loop {
-            // This should match the route tag
-            match parser.next() {
-                Ok(XmlEvent::StartElement {name, attributes, ..}) => {
-                    if name.borrow().local_name == "body" { continue };
+impl RouteConfigRequest {
+    pub fn new() -> Self {
+        RouteConfigRequest::default()
+    }
 
-                    if name.borrow().local_name == "route" {
-                        try!(add_route_to_routes(&mut parser, attributes, &mut routes));
-                    }
-                },
-                Ok(XmlEvent::EndDocument) => break,
-                Ok(_) => continue,
-                Err(_) => break, // Later cover Err
-            }
-        }
+    /// Set agency. If not set, uses the client's.
+    pub fn agency(&mut self, agency: &str) -> &mut Self {
+        self.agency = Some(agency.to_owned());
+        self
+    }
+
+    /// Set route. If not set, gets all routes.
+    pub fn route(&mut self, route: &str) -> &mut Self {
+        self.route = Some(route.to_owned());
+        self
+    }
+
+    /// Leave out paths
+    pub fn terse(&mut self) -> &mut Self {
+        self.terse = true;
+        self
+    }
 
-        Ok(RouteConfig(routes))
+    /// Have NextBus send short titles in place of titles
+    pub fn use_short_titles(&mut self) -> &mut Self {
+        self.use_short_titles = true;
+        self
+    }
+
+    pub fn get(&self, client: &NextBus) -> ::Result<RouteConfig> {
+        self.builder(client).get()
+    }
+
+    pub fn get_partial(&self, client: &NextBus) -> ::Result<Partial<RouteConfig>> {
+        self.builder(client).get_partial()
+    }
+
+    fn builder<'a>(&'a self, client: &NextBus) -> RouteConfigBuilder<'a> {
+        let mut builder = client.route_config();
+        if let Some(ref agency) = self.agency {
+            builder = builder.agency(agency);
+        }
+        if let Some(ref route) = self.route {
+            builder = builder.route(route);
+        }
+        if self.terse {
+            builder = builder.terse();
+        }
+        if self.use_short_titles {
+            builder = builder.use_short_titles();
+        }
+        builder
     }
 }
 
@@ -128,6 +225,8 @@ impl<'a> RouteConfigBuilder<'a> {
 // ===============================================================
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub struct Route {
     tag: String,
     title: String,
@@ -142,50 +241,238 @@ pub struct Route {
     paths: Vec<Path>,
 }
 
+impl Route {
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Color of the route, in hex (e.g. "ff0000")
+    pub fn color(&self) -> &str {
+        &self.color
+    }
+
+    /// Color which contrasts with `color`, for text
+    pub fn opposite_color(&self) -> &str {
+        &self.opposite_color
+    }
+
+    pub fn lat_min(&self) -> f32 {
+        self.lat_min
+    }
+
+    pub fn lat_max(&self) -> f32 {
+        self.lat_max
+    }
+
+    pub fn lon_min(&self) -> f32 {
+        self.lon_min
+    }
+
+    pub fn lon_max(&self) -> f32 {
+        self.lon_max
+    }
+
+    pub fn stops(&self) -> &[Stop] {
+        &self.stops
+    }
+
+    pub fn directions(&self) -> &[Direction] {
+        &self.directions
+    }
+
+    pub fn paths(&self) -> &[Path] {
+        &self.paths
+    }
+}
+
 /// A stop along a Route
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub struct Stop {
     tag: String,
     title: String,
-    lat: String,
-    lon: String,
+    lat: f32,
+    lon: f32,
     short_title: Option<String>,
     stop_id: Option<String>,
 }
 
+impl Stop {
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn lat(&self) -> f32 {
+        self.lat
+    }
+
+    pub fn lon(&self) -> f32 {
+        self.lon
+    }
+
+    pub fn short_title(&self) -> Option<&str> {
+        self.short_title.as_ref().map(String::as_str)
+    }
+
+    /// Title to show in at most `max_len` characters, e.g. 16 on an
+    /// LED sign: the title if it fits, else the short title if it's
+    /// shorter. Neither is cut to fit.
+    pub fn display_title(&self, max_len: usize) -> &str {
+        super::display_title(&self.title, self.short_title(), max_len)
+    }
+
+    /// Public identifier of the stop, as posted for
+    /// riders. Not all agencies have one.
+    pub fn stop_id(&self) -> Option<&str> {
+        self.stop_id.as_ref().map(String::as_str)
+    }
+}
+
+/// Reference to a Stop, by tag, from a Direction
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub struct StopStub {
     tag: String,
 }
 
+impl StopStub {
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+}
+
 /// An itinerary along a route
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub struct Direction {
     tag: String,
     title: String,
     name: String,
+    #[cfg_attr(feature = "serde", serde(rename = "useForUI"))]
     use_for_ui: bool,
     stops: Vec<StopStub>,
 }
 
+impl Direction {
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the direction should be shown to riders
+    pub fn use_for_ui(&self) -> bool {
+        self.use_for_ui
+    }
+
+    /// Tags of the stops along this direction, in order.
+    /// Look them up in the Route's stops for details.
+    pub fn stops(&self) -> &[StopStub] {
+        &self.stops
+    }
+}
+
 /// The coordinates tracing a Route
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub struct Path {
     tag: Option<String>,
     points: Vec<Point>,
 }
 
+impl Path {
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_ref().map(String::as_str)
+    }
+
+    pub fn points(&self) -> &[Point] {
+        &self.points
+    }
+}
+
 /// A coordinate in a Path
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub struct Point {
     lat: f32,
     lon: f32,
 }
 
+impl Point {
+    pub fn lat(&self) -> f32 {
+        self.lat
+    }
+
+    pub fn lon(&self) -> f32 {
+        self.lon
+    }
+}
+
 // ===============================================================
 // Helpers for parsing
 // ===============================================================
 
+// Shared with the offline store, which keeps the raw response.
+// ===============================================================
+
+pub fn parse_route_config<R: Read>(input: R) -> ::Result<RouteConfig> {
+    parse_route_config_partial(input).and_then(Partial::into_result)
+}
+
+/// Like parse_route_config, but if the XML breaks off, returns the
+/// routes parsed before then, with a warning.
+pub fn parse_route_config_partial<R: Read>(input: R) -> ::Result<Partial<RouteConfig>> {
+    // Vec for collecting routes
+    let mut routes = vec![];
+    let res = collect_routes(input, &mut routes);
+    Partial::from_result(RouteConfig(routes), res)
+}
+
+fn collect_routes<R: Read>(input: R, routes: &mut Vec<Route>) -> ::Result<()> {
+    let mut parser = EventReader::new(input);
+
+    loop {
+        // This should match the route tag
+        match parser.next() {
+            Ok(XmlEvent::StartElement {name, attributes, ..}) => {
+                if name.borrow().local_name == "body" { continue };
+
+                if name.borrow().local_name == "Error" {
+                    return Err(super::parse_api_error(&mut parser, attributes));
+                }
+
+                if name.borrow().local_name == "route" {
+                    try!(add_route_to_routes(&mut parser, attributes, routes));
+                }
+            },
+            Ok(XmlEvent::EndDocument) => break,
+            Ok(_) => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(())
+}
+
 // Parsing a Route
 // ===============================================================
 
@@ -211,10 +498,10 @@ fn add_route_to_routes<R: Read>(mut parser: &mut EventReader<R>,
             "title" => title = Some(value.to_owned()),
             "color" => color = Some(value.to_owned()),
             "oppositeColor" => opposite_color = Some(value.to_owned()),
-            "latMin" => lat_min = Some(value.parse().unwrap()),
-            "latMax" => lat_max = Some(value.parse().unwrap()),
-            "lonMin" => lon_min = Some(value.parse().unwrap()),
-            "lonMax" => lon_max = Some(value.parse().unwrap()),
+            "latMin" => lat_min = Some(try!(super::parse_value(value, "route", "latMin"))),
+            "latMax" => lat_max = Some(try!(super::parse_value(value, "route", "latMax"))),
+            "lonMin" => lon_min = Some(try!(super::parse_value(value, "route", "lonMin"))),
+            "lonMax" => lon_max = Some(try!(super::parse_value(value, "route", "lonMax"))),
             _ => (),
         };
     }
@@ -227,14 +514,14 @@ fn add_route_to_routes<R: Read>(mut parser: &mut EventReader<R>,
 
     // Set Route
     routes.push(Route{
-        tag: try!(tag.ok_or(Error::ParseError)),
-        title: try!(title.ok_or(Error::ParseError)),
-        color: try!(color.ok_or(Error::ParseError)),
-        opposite_color: try!(opposite_color.ok_or(Error::ParseError)),
-        lat_min: try!(lat_min.ok_or(Error::ParseError)),
-        lat_max: try!(lat_max.ok_or(Error::ParseError)),
-        lon_min: try!(lon_min.ok_or(Error::ParseError)),
-        lon_max: try!(lon_max.ok_or(Error::ParseError)),
+        tag: try!(super::required(tag, "route", "tag")),
+        title: try!(super::required(title, "route", "title")),
+        color: try!(super::required(color, "route", "color")),
+        opposite_color: try!(super::required(opposite_color, "route", "oppositeColor")),
+        lat_min: try!(super::required(lat_min, "route", "latMin")),
+        lat_max: try!(super::required(lat_max, "route", "latMax")),
+        lon_min: try!(super::required(lon_min, "route", "lonMin")),
+        lon_max: try!(super::required(lon_max, "route", "lonMax")),
         stops: stops,
         directions: directions,
         paths: paths,
@@ -258,7 +545,7 @@ fn parse_route_elements<R: Read>(mut parser: &mut EventReader<R>,
     // The logic for matching:
     // - continue if it's a start element that's a stop
     // - break if it's a start element that's not a stop (and move on)
-    // - break on error
+    // - return on error
     // - break when hit end route
     // - continue if it's any other event (whitespace, etc.)
     loop {
@@ -288,7 +575,7 @@ fn parse_route_elements<R: Read>(mut parser: &mut EventReader<R>,
                     break;
                 }
             },
-            Err(_) => break,
+            Err(err) => return Err(err.into()),
             _ => continue,
         }
     }
@@ -299,8 +586,8 @@ fn add_stop_to_stops(attributes: Vec<OwnedAttribute>,
                      stops: &mut Vec<Stop>) -> ::Result<()> {
     let mut tag = None ;
     let mut title = None;
-    let mut lat = None;
-    let mut lon = None;
+    let mut lat: Option<f32> = None;
+    let mut lon: Option<f32> = None;
     let mut short_title = None;
     let mut stop_id = None;
 
@@ -312,18 +599,18 @@ fn add_stop_to_stops(attributes: Vec<OwnedAttribute>,
         match name {
             "tag" => tag = Some(value.to_owned()),
             "title" => title = Some(value.to_owned()),
-            "lat" => lat = Some(value.parse().unwrap()),
-            "lon" => lon = Some(value.parse().unwrap()),
+            "lat" => lat = Some(try!(super::parse_value(value, "stop", "lat"))),
+            "lon" => lon = Some(try!(super::parse_value(value, "stop", "lon"))),
             "shortTitle" => short_title = Some(value.to_owned()),
             "stopId" => stop_id = Some(value.to_owned()),
             _ => (),
         }
     }
     stops.push(Stop{
-        tag: try!(tag.ok_or(Error::ParseError)),
-        title: try!(title.ok_or(Error::ParseError)),
-        lat: try!(lat.ok_or(Error::ParseError)),
-        lon: try!(lon.ok_or(Error::ParseError)),
+        tag: try!(super::required(tag, "stop", "tag")),
+        title: try!(super::required(title, "stop", "title")),
+        lat: try!(super::required(lat, "stop", "lat")),
+        lon: try!(super::required(lon, "stop", "lon")),
         short_title: short_title,
         stop_id: stop_id,
     });
@@ -352,7 +639,8 @@ fn parse_direction<R: Read>(parser: &mut EventReader<R>,
             "tag" => tag = Some(value.to_owned()),
             "title" => title = Some(value.to_owned()),
             "name" => name = Some(value.to_owned()),
-            "useForUI" => use_for_ui = Some(value.parse().unwrap()),
+            "useForUI" =>
+                use_for_ui = Some(try!(super::parse_value(value, "direction", "useForUI"))),
             _ => (),
         };
     }
@@ -361,7 +649,7 @@ fn parse_direction<R: Read>(parser: &mut EventReader<R>,
     // The logic for matching:
     // - continue if it's a start element that's a stop
     // - break if it's an end element for direction (The next one should be matching)
-    // - break on error
+    // - return on error
     // - continue if it's any other event (whitespace, etc.)
     let mut stops = Vec::new();
     loop {
@@ -378,15 +666,15 @@ fn parse_direction<R: Read>(parser: &mut EventReader<R>,
                     break;
                 }
             },
-            Err(_) => break,
+            Err(err) => return Err(err.into()),
             _ => continue,
         }
     }
     directions.push(Direction {
-        tag: try!(tag.ok_or(Error::ParseError)),
-        title: try!(title.ok_or(Error::ParseError)),
-        name: try!(name.ok_or(Error::ParseError)),
-        use_for_ui: try!(use_for_ui.ok_or(Error::ParseError)),
+        tag: try!(super::required(tag, "direction", "tag")),
+        title: try!(super::required(title, "direction", "title")),
+        name: try!(super::required(name, "direction", "name")),
+        use_for_ui: try!(super::required(use_for_ui, "direction", "useForUI")),
         stops: stops,
     });
     Ok(())
@@ -407,7 +695,7 @@ fn add_stop_to_direction(attributes: Vec<OwnedAttribute>,
         };
     }
     stops.push(StopStub {
-        tag: try!(tag.ok_or(Error::ParseError)),
+        tag: try!(super::required(tag, "stop", "tag")),
     });
     Ok(())
 }
@@ -425,7 +713,7 @@ fn parse_path<R: Read>(parser: &mut EventReader<R>,
     // The logic for matching:
     // - continue if it's a start element that's a tag or point
     // - break if it's an end element for path (The next one should be matching)
-    // - break on error
+    // - return on error
     // - continue if it's any other event (whitespace, etc.)
     let mut points = Vec::new();
     loop {
@@ -454,7 +742,7 @@ fn parse_path<R: Read>(parser: &mut EventReader<R>,
                     break;
                 }
             },
-            Err(_) => break,
+            Err(err) => return Err(err.into()),
             _ => continue,
         }
     }
@@ -476,14 +764,14 @@ fn add_point_to_path(attributes: Vec<OwnedAttribute>,
         let value = attribute.value;
 
         match name {
-            "lat" => lat = Some(value.parse().unwrap()),
-            "lon" => lon = Some(value.parse().unwrap()),
+            "lat" => lat = Some(try!(super::parse_value(value, "point", "lat"))),
+            "lon" => lon = Some(try!(super::parse_value(value, "point", "lon"))),
             _ => (),
         };
     }
     points.push(Point {
-        lat: try!(lat.ok_or(Error::ParseError)),
-        lon: try!(lon.ok_or(Error::ParseError)),
+        lat: try!(super::required(lat, "point", "lat")),
+        lon: try!(super::required(lon, "point", "lon")),
     });
     Ok(())
 }
@@ -493,36 +781,175 @@ fn add_point_to_path(attributes: Vec<OwnedAttribute>,
 
 #[cfg(test)]
 mod test {
+    use api::test::assert_invalid_attributes;
+    use error::Error;
+    use std::io::Cursor;
+    use transport::fixture_client;
     use super::*;
 
+    const GOOD_ROUTE_CONFIG_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright Massachusetts Institute of Technology 2016.\">
+        <route tag=\"boston\" title=\"Boston Daytime\" color=\"9999ff\"
+            oppositeColor=\"000000\" latMin=\"42.3398\" latMax=\"42.3617\"
+            lonMin=\"-71.1166\" lonMax=\"-71.0716\">
+        <stop tag=\"massave\" title=\"84 Mass Ave\" shortTitle=\"84 Mass\"
+            lat=\"42.3592\" lon=\"-71.0938\" stopId=\"01\"/>
+        <stop tag=\"mccrmk\" title=\"McCormick Hall\" lat=\"42.3573\" lon=\"-71.0946\"/>
+        <direction tag=\"frcamp\" title=\"from campus\" name=\"\" useForUI=\"true\">
+        <stop tag=\"massave\"/>
+        <stop tag=\"mccrmk\"/>
+        </direction>
+        <path>
+        <tag id=\"boston_massave\"/>
+        <point lat=\"42.3592\" lon=\"-71.0938\"/>
+        <point lat=\"42.3573\" lon=\"-71.0946\"/>
+        </path>
+        </route>
+        </body>";
+
+    #[test]
+    fn parse_good_xml() {
+        let buffer = Cursor::new(GOOD_ROUTE_CONFIG_XML);
+        let route_config = RouteConfigBuilder::from_xml(buffer).unwrap();
+        let routes: Vec<_> = route_config.into_iter().collect();
+        assert_eq!(routes.len(), 1);
+
+        let route = &routes[0];
+        assert_eq!(route.tag(), "boston");
+        assert_eq!(route.title(), "Boston Daytime");
+        assert_eq!(route.color(), "9999ff");
+        assert_eq!(route.opposite_color(), "000000");
+        assert_eq!(route.lat_min(), 42.3398);
+        assert_eq!(route.lon_max(), -71.0716);
+
+        let stops = route.stops();
+        assert_eq!(stops.len(), 2);
+        assert_eq!(stops[0].tag(), "massave");
+        assert_eq!(stops[0].short_title(), Some("84 Mass"));
+        assert_eq!(stops[0].display_title(16), "84 Mass Ave");
+        assert_eq!(stops[0].display_title(8), "84 Mass");
+        assert_eq!(stops[0].stop_id(), Some("01"));
+        assert_eq!(stops[1].lat(), 42.3573);
+        assert_eq!(stops[1].short_title(), None);
+
+        let direction = &route.directions()[0];
+        assert_eq!(direction.tag(), "frcamp");
+        assert!(direction.use_for_ui());
+        let stop_tags: Vec<_> = direction.stops().iter().map(|stop| stop.tag()).collect();
+        assert_eq!(stop_tags, vec!["massave", "mccrmk"]);
+
+        let path = &route.paths()[0];
+        assert_eq!(path.tag(), Some("boston_massave"));
+        assert_eq!(path.points().len(), 2);
+        assert_eq!(path.points()[1].lon(), -71.0946);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_field_names() {
+        use serde_json;
+
+        let buffer = Cursor::new(GOOD_ROUTE_CONFIG_XML);
+        let route_config = RouteConfigBuilder::from_xml(buffer).unwrap();
+
+        let json = serde_json::to_string(&route_config).unwrap();
+        assert!(json.contains("\"oppositeColor\":\"000000\""));
+        assert!(json.contains("\"stopId\":\"01\""));
+        assert!(json.contains("\"useForUI\":true"));
+
+        let from_json: RouteConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&from_json).unwrap(), json);
+    }
+
+    #[test]
+    fn parse_missing_attribute() {
+        let xml = GOOD_ROUTE_CONFIG_XML.replace("title=\"McCormick Hall\" lat=\"42.3573\"",
+                                                "title=\"McCormick Hall\"");
+        match RouteConfigBuilder::from_xml(Cursor::new(xml)) {
+            Err(Error::MissingAttribute { element, attribute }) => {
+                assert_eq!(element, "stop");
+                assert_eq!(attribute, "lat");
+            },
+            res => panic!("Expected MissingAttribute, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn parse_invalid_attribute() {
+        // Corrupted value in the response, and where it should fail
+        let cases = vec![
+            ("latMin=\"42.3398\"", "latMin=\"\"", "route", "latMin"),
+            ("latMax=\"42.3617\"", "latMax=\"north\"", "route", "latMax"),
+            ("lonMin=\"-71.1166\"", "lonMin=\"-71.11.66\"", "route", "lonMin"),
+            ("lonMax=\"-71.0716\"", "lonMax=\"west\"", "route", "lonMax"),
+            ("lat=\"42.3592\" lon=\"-71.0938\" stopId", "lat=\"x\" lon=\"-71.0938\" stopId",
+             "stop", "lat"),
+            ("lat=\"42.3592\" lon=\"-71.0938\" stopId", "lat=\"42.3592\" lon=\"\" stopId",
+             "stop", "lon"),
+            ("useForUI=\"true\"", "useForUI=\"yes\"", "direction", "useForUI"),
+            ("<point lat=\"42.3592\"", "<point lat=\"42,3592\"", "point", "lat"),
+            ("lon=\"-71.0946\"/>\n        </path>", "lon=\"?\"/>\n        </path>", "point", "lon"),
+        ];
+        assert_invalid_attributes(GOOD_ROUTE_CONFIG_XML, cases, RouteConfigBuilder::from_xml);
+    }
+
+    #[test]
+    fn parse_truncated_xml() {
+        // Cut short in the middle of a route
+        let xml = GOOD_ROUTE_CONFIG_XML.replace("</body>", "<route tag=\"tech\" title=");
+
+        match RouteConfigBuilder::from_xml(Cursor::new(xml.clone())) {
+            Err(Error::Xml { .. }) => (),
+            res => panic!("Expected Xml error, got {:?}", res),
+        }
+
+        // Leniently, the routes before it
+        let partial = parse_route_config_partial(Cursor::new(xml)).unwrap();
+        assert!(!partial.is_complete());
+        let tags: Vec<_> = partial.value().into_iter().map(|route| route.tag()).collect();
+        assert_eq!(tags, vec!["boston"]);
+        assert_eq!(partial.warnings().len(), 1);
+        match partial.warnings()[0] {
+            Error::Xml { .. } => (),
+            ref warning => panic!("Expected Xml warning, got {:?}", warning),
+        }
+    }
+
     #[test]
-    #[ignore]
     fn should_get_one_route_config() {
-        let routes = RouteConfigBuilder::new()
+        let routes = RouteConfigBuilder::new(fixture_client())
             .agency("mit")
             .route("saferidecampshut")
             .get()
             .unwrap();
-        for route in routes {
-            println!("{:?}", route);
-            println!("\n");
+        assert_eq!(routes.0.len(), 1);
+        let route = &routes.0[0];
+        assert_eq!(route.tag(), "saferidecampshut");
+        assert_eq!(route.stops().len(), 3);
+        assert_eq!(route.directions()[0].stops().len(), 3);
+        assert_eq!(route.paths()[0].points().len(), 3);
+    }
+
+    #[test]
+    fn should_get_route_config_repeatedly() {
+        let mut request = RouteConfigRequest::new();
+        request.agency("mit").route("saferidecampshut");
+        let client = fixture_client();
+        for _ in 0..2 {
+            let routes = request.get(&client).unwrap();
+            assert_eq!(routes.0[0].tag(), "saferidecampshut");
         }
-        assert!(false);
     }
 
     #[test]
-    #[ignore]
     fn should_get_many_route_config() {
-        let routes = RouteConfigBuilder::new()
+        let routes = RouteConfigBuilder::new(fixture_client())
             .agency("moorpark")
             .get()
             .unwrap();
-        for route in &routes {
-            println!("{:?}", route);
-            println!("\n");
-        }
-        println!("Total number of routes: {}", routes.0.len());
-        assert!(false);
+        let tags: Vec<_> = routes.into_iter().map(|route| route.tag().to_owned()).collect();
+        assert_eq!(tags, vec!["1", "2"]);
     }
 }
 
@@ -1,6 +1,7 @@
 //! Next Bus Route List Command
 
-use error::Error;
+use NextBus;
+use api::Partial;
 use request::{Command, Request};
 use std::io::Read;
 use xml::reader::{EventReader, XmlEvent};
@@ -9,6 +10,7 @@ use xml::reader::{EventReader, XmlEvent};
 /// List of routes. Maps directly from Nextbus response.
 /// Contains vec of stub information for each route.
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RouteList(Vec<Route>);
 
 impl RouteList {
@@ -40,43 +42,82 @@ impl<'a> IntoIterator for &'a RouteList {
 // ===============================================================
 
 pub struct RouteListBuilder<'a> {
+    client: NextBus,
     agency: Option<&'a str>,
+    use_short_titles: bool,
 }
 
 impl<'a> RouteListBuilder<'a> {
-    pub fn new() -> Self {
-        RouteListBuilder { agency: None }
+    pub fn new(client: NextBus) -> Self {
+        RouteListBuilder {
+            client: client,
+            agency: None,
+            use_short_titles: false,
+        }
+    }
+
+    /// Builder to set the base url for this request only,
+    /// overriding the client's.
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.client.base_url = base_url.to_owned();
+        self
     }
 
     /// Builder to set agency
-    pub fn agency(&mut self, agency: &'a str) -> &Self {
+    pub fn agency(mut self, agency: &'a str) -> Self {
         self.agency = Some(agency);
         self
     }
 
+    /// Builder to have NextBus send short titles in place of
+    /// titles, where there are any.
+    pub fn use_short_titles(mut self) -> Self {
+        self.use_short_titles = true;
+        self
+    }
+
     pub fn get(&self) -> ::Result<RouteList> {
+        self.get_partial().and_then(Partial::into_result)
+    }
+
+    /// Like get, but if the response breaks off (e.g. the body is
+    /// cut short), returns the routes parsed before then, with a
+    /// warning.
+    pub fn get_partial(&self) -> ::Result<Partial<RouteList>> {
         // Check if agency is none. If so, send error.
-        let agency = try!(self.agency.ok_or(Error::BuildCommandError));
+        let agency = try!(self.client.agency_or_default(self.agency));
 
-        // Request and get back
-        let res = try!(Request::new()
-            .command(Command::RouteList)
-            .agency(agency)
-            .send());
+        // Request, and parse xml into route list struct
+        let mut request = Request::new();
+        request.command(Command::RouteList).agency(agency);
+        if self.use_short_titles {
+            request.use_short_titles();
+        }
+        self.client.get(&request, Self::from_xml_partial)
+    }
 
-        // Parse xml into route list struct
-        Self::from_xml(res)
+    #[cfg(test)]
+    pub(super) fn from_xml<R: Read>(input: R) -> ::Result<RouteList> {
+        Self::from_xml_partial(input).and_then(Partial::into_result)
     }
 
-    fn from_xml<R: Read>(input: R) -> ::Result<RouteList> {
+    fn from_xml_partial<R: Read>(input: R) -> ::Result<Partial<RouteList>> {
         // Vec for collecting routes
         let mut routes = vec![];
+        let res = Self::parse_routes(input, &mut routes);
+        Partial::from_result(RouteList(routes), res)
+    }
 
-        let parser = EventReader::new(input);
+    fn parse_routes<R: Read>(input: R, routes: &mut Vec<Route>) -> ::Result<()> {
+        let mut parser = EventReader::new(input);
 
-        for event in parser {
-            match event {
+        loop {
+            match parser.next() {
                 Ok(XmlEvent::StartElement {name, attributes, ..}) => {
+                    if name.borrow().local_name == "Error" {
+                        return Err(super::parse_api_error(&mut parser, attributes));
+                    }
+
                     if name.borrow().local_name == "route" {
                         let mut tag = None ;
                         let mut title = None;
@@ -96,17 +137,68 @@ impl<'a> RouteListBuilder<'a> {
                         }
 
                         routes.push(Route{
-                            tag: try!(tag.ok_or(Error::ParseError)),
-                            title: try!(title.ok_or(Error::ParseError)),
+                            tag: try!(super::required(tag, "route", "tag")),
+                            title: try!(super::required(title, "route", "title")),
                             short_title: short_title,
                         });
                     }
                 },
-                _ => (),
+                Ok(XmlEvent::EndDocument) => break,
+                Ok(_) => continue,
+                Err(err) => return Err(err.into()),
             }
         }
 
-        Ok(RouteList(routes))
+        Ok(())
+    }
+}
+
+// Owned request
+// ===============================================================
+
+/// A route list request which owns its parameters, so it can be
+/// kept (e.g. built from config), cloned, sent across threads and
+/// run repeatedly with a client.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RouteListRequest {
+    agency: Option<String>,
+    use_short_titles: bool,
+}
+
+impl RouteListRequest {
+    pub fn new() -> Self {
+        RouteListRequest::default()
+    }
+
+    /// Set agency. If not set, uses the client's.
+    pub fn agency(&mut self, agency: &str) -> &mut Self {
+        self.agency = Some(agency.to_owned());
+        self
+    }
+
+    /// Have NextBus send short titles in place of titles
+    pub fn use_short_titles(&mut self) -> &mut Self {
+        self.use_short_titles = true;
+        self
+    }
+
+    pub fn get(&self, client: &NextBus) -> ::Result<RouteList> {
+        self.builder(client).get()
+    }
+
+    pub fn get_partial(&self, client: &NextBus) -> ::Result<Partial<RouteList>> {
+        self.builder(client).get_partial()
+    }
+
+    fn builder<'a>(&'a self, client: &NextBus) -> RouteListBuilder<'a> {
+        let mut builder = client.route_list();
+        if let Some(ref agency) = self.agency {
+            builder = builder.agency(agency);
+        }
+        if self.use_short_titles {
+            builder = builder.use_short_titles();
+        }
+        builder
     }
 }
 
@@ -115,6 +207,8 @@ impl<'a> RouteListBuilder<'a> {
 
 /// Used for parsing RouteList Nextbus response.
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub struct Route {
     tag: String,
     title: String,
@@ -129,6 +223,25 @@ impl Route {
             short_title: short_title,
         }
     }
+
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn short_title(&self) -> Option<&str> {
+        self.short_title.as_ref().map(String::as_str)
+    }
+
+    /// Title to show in at most `max_len` characters, e.g. 16 on an
+    /// LED sign: the title if it fits, else the short title if it's
+    /// shorter. Neither is cut to fit.
+    pub fn display_title(&self, max_len: usize) -> &str {
+        super::display_title(&self.title, self.short_title(), max_len)
+    }
 }
 
 // Tests
@@ -137,6 +250,7 @@ impl Route {
 #[cfg(test)]
 mod test {
     use std::io::Cursor;
+    use transport::fixture_client;
     use super::*;
 
 const GOOD_ROUTE_XML: &'static str = "
@@ -211,17 +325,44 @@ const EXTRA_ATTRIBUTE_ROUTE_XML: &'static str = "
     }
 
     #[test]
-    #[ignore]
+    fn should_get_routes_with_short_titles() {
+        let routes = RouteListBuilder::new(fixture_client()).agency("mit").get().unwrap();
+        let titles: Vec<_> = (&routes).into_iter().map(|route| route.display_title(16)).collect();
+        assert_eq!(titles, vec!["Boston Daytime", "Kendall-Charles", "Saferide Campus Shuttle",
+                                "Tech Shuttle"]);
+
+        // NextBus puts the short titles in place of the titles
+        let routes = RouteListBuilder::new(fixture_client())
+            .agency("mit")
+            .use_short_titles()
+            .get()
+            .unwrap();
+        let titles: Vec<_> = routes.into_iter().map(|route| route.title().to_owned()).collect();
+        assert_eq!(titles[1], "Kendall-Charles");
+    }
+
+    #[test]
+    fn should_get_routes_repeatedly() {
+        let mut request = RouteListRequest::new();
+        request.agency("mit");
+        let client = fixture_client();
+        let first = request.get(&client).unwrap();
+
+        // Moved to another thread, and run again there
+        let other = request.clone();
+        let second = ::std::thread::spawn(move || other.get(&client).unwrap()).join().unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first.into_iter().count(), 4);
+    }
+
+    #[test]
     fn should_get_routes() {
-        let routes = RouteListBuilder::new()
+        let routes = RouteListBuilder::new(fixture_client())
             .agency("mit")
             .get()
             .unwrap();
-        for route in routes {
-            println!("route");
-            println!("{:?}\n", route);
-        }
-        assert!(false);
+        let tags: Vec<_> = routes.into_iter().map(|route| route.tag().to_owned()).collect();
+        assert_eq!(tags, vec!["boston", "kendchar", "saferidecampshut", "tech"]);
     }
 }
 
@@ -0,0 +1,603 @@
+//! Next Bus Schedule Command
+
+use NextBus;
+use api::{Partial, Set, Unset};
+use request::{Command, Request};
+use std::io::Read;
+use xml::attribute::OwnedAttribute;
+use xml::reader::{EventReader, XmlEvent};
+
+/// Schedule for a route. Maps directly from Nextbus response.
+/// Contains one table per direction and service class
+/// (e.g. "wkd", "sat", "sun").
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Schedule(Vec<Route>);
+
+impl Schedule {
+    pub fn new(routes: Vec<Route>) -> Self {
+        Schedule(routes)
+    }
+}
+
+impl IntoIterator for Schedule {
+    type Item = Route;
+    type IntoIter = ::std::vec::IntoIter<Route>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Schedule {
+    type Item = &'a Route;
+    type IntoIter = ::std::slice::Iter<'a, Route>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let &Schedule(ref routes) = self;
+        routes.iter()
+    }
+}
+
+// Builder
+// ===============================================================
+
+/// Builder for the schedule of a route. The route is required, so
+/// `get` is only there once it's set.
+pub struct ScheduleBuilder<'a, R = Unset> {
+    client: NextBus,
+    agency: Option<&'a str>,
+    route: R,
+}
+
+impl<'a> ScheduleBuilder<'a> {
+    pub fn new(client: NextBus) -> Self {
+        ScheduleBuilder {
+            client: client,
+            agency: None,
+            route: Unset,
+        }
+    }
+}
+
+impl<'a, R> ScheduleBuilder<'a, R> {
+    /// Builder to set the base url for this request only,
+    /// overriding the client's.
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.client.base_url = base_url.to_owned();
+        self
+    }
+
+    /// Builder to set agency
+    pub fn agency(mut self, agency: &'a str) -> Self {
+        self.agency = Some(agency);
+        self
+    }
+
+    /// Builder to set route
+    pub fn route(self, route: &'a str) -> ScheduleBuilder<'a, Set<&'a str>> {
+        ScheduleBuilder {
+            client: self.client,
+            agency: self.agency,
+            route: Set(route),
+        }
+    }
+}
+
+impl<'a> ScheduleBuilder<'a, Set<&'a str>> {
+    pub fn get(&self) -> ::Result<Schedule> {
+        self.get_partial().and_then(Partial::into_result)
+    }
+
+    /// Like get, but if the response breaks off (e.g. the body is
+    /// cut short), returns the routes parsed before then, with a
+    /// warning.
+    pub fn get_partial(&self) -> ::Result<Partial<Schedule>> {
+        // Check if agency is none. If so, send error.
+        let agency = try!(self.client.agency_or_default(self.agency));
+
+        // Request, and parse xml into schedule struct
+        self.client.get(Request::new()
+            .command(Command::Schedule)
+            .agency(agency)
+            .route(self.route.0), ScheduleBuilder::from_xml_partial)
+    }
+}
+
+impl<'a> ScheduleBuilder<'a> {
+    #[cfg(test)]
+    pub(super) fn from_xml<R: Read>(input: R) -> ::Result<Schedule> {
+        Self::from_xml_partial(input).and_then(Partial::into_result)
+    }
+
+    fn from_xml_partial<R: Read>(input: R) -> ::Result<Partial<Schedule>> {
+        // Vec for collecting one table per direction and service class
+        let mut routes = vec![];
+        let res = Self::parse_routes(input, &mut routes);
+        Partial::from_result(Schedule(routes), res)
+    }
+
+    fn parse_routes<R: Read>(input: R, routes: &mut Vec<Route>) -> ::Result<()> {
+        let mut parser = EventReader::new(input);
+
+        loop {
+            match parser.next() {
+                Ok(XmlEvent::StartElement {name, attributes, ..}) => {
+                    if name.borrow().local_name == "Error" {
+                        return Err(super::parse_api_error(&mut parser, attributes));
+                    }
+
+                    if name.borrow().local_name == "route" {
+                        try!(add_route_to_routes(&mut parser, attributes, routes));
+                    }
+                },
+                Ok(XmlEvent::EndDocument) => break,
+                Ok(_) => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Components of Schedule
+// ===============================================================
+
+/// The schedule table for one direction and service class of a route.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Route {
+    tag: String,
+    title: String,
+    schedule_class: String,
+    service_class: String,
+    direction: String,
+    header: Vec<HeaderStop>,
+    blocks: Vec<Block>,
+}
+
+impl Route {
+    pub fn new(tag: String,
+               title: String,
+               schedule_class: String,
+               service_class: String,
+               direction: String,
+               header: Vec<HeaderStop>,
+               blocks: Vec<Block>) -> Self {
+        Route {
+            tag: tag,
+            title: title,
+            schedule_class: schedule_class,
+            service_class: service_class,
+            direction: direction,
+            header: header,
+            blocks: blocks,
+        }
+    }
+
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn schedule_class(&self) -> &str {
+        &self.schedule_class
+    }
+
+    pub fn service_class(&self) -> &str {
+        &self.service_class
+    }
+
+    pub fn direction(&self) -> &str {
+        &self.direction
+    }
+
+    pub fn header(&self) -> &[HeaderStop] {
+        &self.header
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+}
+
+/// A column of the schedule table: the timepoint stops
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct HeaderStop {
+    tag: String,
+    title: String,
+}
+
+impl HeaderStop {
+    pub fn new(tag: String, title: String) -> Self {
+        HeaderStop {
+            tag: tag,
+            title: title,
+        }
+    }
+
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+}
+
+/// A row of the schedule table: one run of a vehicle block
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Block {
+    #[cfg_attr(feature = "serde", serde(rename = "blockID"))]
+    id: String,
+    stops: Vec<ScheduledStop>,
+}
+
+impl Block {
+    pub fn new(id: String, stops: Vec<ScheduledStop>) -> Self {
+        Block {
+            id: id,
+            stops: stops,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn stops(&self) -> &[ScheduledStop] {
+        &self.stops
+    }
+}
+
+/// A cell of the schedule table.
+///
+/// `epoch_time` is milliseconds since midnight and `time` is the
+/// "HH:MM:SS" string. Both are None when the block doesn't serve the
+/// stop (Nextbus sends `epochTime="-1"` and "--").
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct ScheduledStop {
+    tag: String,
+    epoch_time: Option<usize>,
+    time: Option<String>,
+}
+
+impl ScheduledStop {
+    pub fn new(tag: String, epoch_time: Option<usize>, time: Option<String>) -> Self {
+        ScheduledStop {
+            tag: tag,
+            epoch_time: epoch_time,
+            time: time,
+        }
+    }
+
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Msec since midnight, None if the block skips this stop
+    pub fn epoch_time(&self) -> Option<usize> {
+        self.epoch_time
+    }
+
+    /// "HH:MM:SS", None if the block skips this stop
+    pub fn time(&self) -> Option<&str> {
+        self.time.as_ref().map(String::as_str)
+    }
+}
+
+// ===============================================================
+// Helpers for parsing
+// ===============================================================
+
+// Parsing a Route
+// ===============================================================
+
+fn add_route_to_routes<R: Read>(mut parser: &mut EventReader<R>,
+                                attributes: Vec<OwnedAttribute>,
+                                routes: &mut Vec<Route>) -> ::Result<()> {
+    let mut tag = None;
+    let mut title = None;
+    let mut schedule_class = None;
+    let mut service_class = None;
+    let mut direction = None;
+
+    for attribute in attributes {
+        let attribute = attribute.borrow();
+        let name = attribute.name.local_name;
+        let value = attribute.value;
+
+        match name {
+            "tag" => tag = Some(value.to_owned()),
+            "title" => title = Some(value.to_owned()),
+            "scheduleClass" => schedule_class = Some(value.to_owned()),
+            "serviceClass" => service_class = Some(value.to_owned()),
+            "direction" => direction = Some(value.to_owned()),
+            _ => (),
+        };
+    }
+
+    // Now get header and blocks for this route
+    // The logic for matching:
+    // - parse header if it's a start element that's a header
+    // - parse block if it's a start element that's a tr
+    // - break when hit end route
+    // - return on error
+    // - continue if it's any other event (whitespace, etc.)
+    let mut header = Vec::new();
+    let mut blocks = Vec::new();
+    loop {
+        match parser.next() {
+            Ok(XmlEvent::StartElement {name, attributes, ..}) => {
+                let name = name.borrow().local_name;
+                if name == "header" {
+                    try!(parse_header(&mut parser, &mut header));
+                } else if name == "tr" {
+                    try!(add_block_to_blocks(&mut parser, attributes, &mut blocks));
+                }
+            },
+            Ok(XmlEvent::EndElement {name, ..}) => {
+                if name.borrow().local_name == "route" {
+                    break;
+                }
+            },
+            Err(err) => return Err(err.into()),
+            _ => continue,
+        }
+    }
+
+    routes.push(Route {
+        tag: try!(super::required(tag, "route", "tag")),
+        title: try!(super::required(title, "route", "title")),
+        schedule_class: try!(super::required(schedule_class, "route", "scheduleClass")),
+        service_class: try!(super::required(service_class, "route", "serviceClass")),
+        direction: try!(super::required(direction, "route", "direction")),
+        header: header,
+        blocks: blocks,
+    });
+
+    Ok(())
+}
+
+// Parsing the Header
+// ===============================================================
+
+fn parse_header<R: Read>(mut parser: &mut EventReader<R>,
+                         header: &mut Vec<HeaderStop>) -> ::Result<()> {
+    loop {
+        match parser.next() {
+            Ok(XmlEvent::StartElement {name, attributes, ..}) => {
+                if name.borrow().local_name == "stop" {
+                    let mut tag = None;
+
+                    for attribute in attributes {
+                        let attribute = attribute.borrow();
+                        let name = attribute.name.local_name;
+                        let value = attribute.value;
+
+                        match name {
+                            "tag" => tag = Some(value.to_owned()),
+                            _ => (),
+                        };
+                    }
+
+                    // Title of the stop is the text of the element
+                    let title = try!(super::parse_text(&mut parser));
+
+                    header.push(HeaderStop {
+                        tag: try!(super::required(tag, "stop", "tag")),
+                        title: title,
+                    });
+                }
+            },
+            Ok(XmlEvent::EndElement {name, ..}) => {
+                if name.borrow().local_name == "header" {
+                    break;
+                }
+            },
+            Err(err) => return Err(err.into()),
+            _ => continue,
+        }
+    }
+    Ok(())
+}
+
+// Parsing a Block
+// ===============================================================
+
+fn add_block_to_blocks<R: Read>(mut parser: &mut EventReader<R>,
+                                attributes: Vec<OwnedAttribute>,
+                                blocks: &mut Vec<Block>) -> ::Result<()> {
+    let mut id = None;
+
+    for attribute in attributes {
+        let attribute = attribute.borrow();
+        let name = attribute.name.local_name;
+        let value = attribute.value;
+
+        match name {
+            "blockID" => id = Some(value.to_owned()),
+            _ => (),
+        };
+    }
+
+    let mut stops = Vec::new();
+    loop {
+        match parser.next() {
+            Ok(XmlEvent::StartElement {name, attributes, ..}) => {
+                if name.borrow().local_name == "stop" {
+                    try!(add_stop_to_stops(&mut parser, attributes, &mut stops));
+                }
+            },
+            Ok(XmlEvent::EndElement {name, ..}) => {
+                if name.borrow().local_name == "tr" {
+                    break;
+                }
+            },
+            Err(err) => return Err(err.into()),
+            _ => continue,
+        }
+    }
+
+    blocks.push(Block {
+        id: try!(super::required(id, "tr", "blockID")),
+        stops: stops,
+    });
+    Ok(())
+}
+
+fn add_stop_to_stops<R: Read>(parser: &mut EventReader<R>,
+                              attributes: Vec<OwnedAttribute>,
+                              stops: &mut Vec<ScheduledStop>) -> ::Result<()> {
+    let mut tag = None;
+    let mut epoch_time: Option<isize> = None;
+
+    for attribute in attributes {
+        let attribute = attribute.borrow();
+        let name = attribute.name.local_name;
+        let value = attribute.value;
+
+        match name {
+            "tag" => tag = Some(value.to_owned()),
+            "epochTime" => epoch_time = Some(try!(super::parse_value(value, "stop", "epochTime"))),
+            _ => (),
+        };
+    }
+
+    // "HH:MM:SS" is the text of the element
+    let time = try!(super::parse_text(parser));
+
+    // -1 and "--" mean the block doesn't stop here
+    let epoch_time = try!(super::required(epoch_time, "stop", "epochTime"));
+    let epoch_time = if epoch_time < 0 { None } else { Some(epoch_time as usize) };
+    let time = if time == "--" { None } else { Some(time) };
+
+    stops.push(ScheduledStop {
+        tag: try!(super::required(tag, "stop", "tag")),
+        epoch_time: epoch_time,
+        time: time,
+    });
+    Ok(())
+}
+
+// Tests
+// ===============================================================
+
+#[cfg(test)]
+mod test {
+    use api::test::assert_invalid_attributes;
+    use std::io::Cursor;
+    use transport::fixture_client;
+    use super::*;
+
+    const GOOD_SCHEDULE_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright San Francisco Muni 2016.\">
+        <route tag=\"E\" title=\"E-Embarcadero\" scheduleClass=\"2016T_FALL\"
+            serviceClass=\"wkd\" direction=\"Inbound\">
+        <header>
+        <stop tag=\"5240\">Fisherman&apos;s Wharf</stop>
+        <stop tag=\"7145\">King St &amp; 2nd St</stop>
+        </header>
+        <tr blockID=\"8401\">
+        <stop tag=\"5240\" epochTime=\"32400000\">09:00:00</stop>
+        <stop tag=\"7145\" epochTime=\"-1\">--</stop>
+        </tr>
+        </route>
+        <route tag=\"E\" title=\"E-Embarcadero\" scheduleClass=\"2016T_FALL\"
+            serviceClass=\"sat\" direction=\"Inbound\">
+        <header>
+        <stop tag=\"5240\">Fisherman&apos;s Wharf</stop>
+        </header>
+        </route>
+        </body>";
+
+    const MISSING_SERVICE_CLASS_SCHEDULE_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright San Francisco Muni 2016.\">
+        <route tag=\"E\" title=\"E-Embarcadero\" scheduleClass=\"2016T_FALL\"
+            direction=\"Inbound\">
+        </route>
+        </body>";
+
+    #[test]
+    fn parse_good_xml() {
+        let buffer = Cursor::new(GOOD_SCHEDULE_XML);
+        let schedule = ScheduleBuilder::from_xml(buffer).unwrap();
+
+        let weekday = Route::new(
+            "E".to_owned(),
+            "E-Embarcadero".to_owned(),
+            "2016T_FALL".to_owned(),
+            "wkd".to_owned(),
+            "Inbound".to_owned(),
+            vec![HeaderStop::new("5240".to_owned(), "Fisherman's Wharf".to_owned()),
+                 HeaderStop::new("7145".to_owned(), "King St & 2nd St".to_owned())],
+            vec![Block::new("8401".to_owned(), vec![
+                ScheduledStop::new("5240".to_owned(),
+                                   Some(32400000),
+                                   Some("09:00:00".to_owned())),
+                ScheduledStop::new("7145".to_owned(), None, None),
+            ])]);
+        let saturday = Route::new(
+            "E".to_owned(),
+            "E-Embarcadero".to_owned(),
+            "2016T_FALL".to_owned(),
+            "sat".to_owned(),
+            "Inbound".to_owned(),
+            vec![HeaderStop::new("5240".to_owned(), "Fisherman's Wharf".to_owned())],
+            vec![]);
+        let test_schedule = Schedule::new(vec![weekday, saturday]);
+
+        assert_eq!(schedule, test_schedule);
+    }
+
+    #[test]
+    #[should_panic]
+    fn parse_bad_xml_missing_service_class() {
+        let buffer = Cursor::new(MISSING_SERVICE_CLASS_SCHEDULE_XML);
+        ScheduleBuilder::from_xml(buffer).unwrap();
+    }
+
+    #[test]
+    fn parse_invalid_attribute() {
+        // Corrupted value in the response, and where it should fail
+        let cases = vec![
+            ("epochTime=\"32400000\"", "epochTime=\"09:00:00\"", "stop", "epochTime"),
+            ("epochTime=\"-1\"", "epochTime=\"--\"", "stop", "epochTime"),
+        ];
+        assert_invalid_attributes(GOOD_SCHEDULE_XML, cases, ScheduleBuilder::from_xml);
+    }
+
+    #[test]
+    fn should_get_schedule() {
+        let schedule = ScheduleBuilder::new(fixture_client())
+            .agency("mit")
+            .route("saferidecampshut")
+            .get()
+            .unwrap();
+        let routes: Vec<_> = schedule.into_iter().collect();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].header().len(), 2);
+        assert_eq!(routes[0].blocks().len(), 2);
+        assert_eq!(routes[0].blocks()[1].stops()[1].time(), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn parse_bad_xml_invalid_epoch_time() {
+        let xml = GOOD_SCHEDULE_XML.replace("epochTime=\"32400000\"", "epochTime=\"9am\"");
+        ScheduleBuilder::from_xml(Cursor::new(xml)).unwrap();
+    }
+}
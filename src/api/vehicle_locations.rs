@@ -0,0 +1,438 @@
+//! Next Bus Vehicle Locations Command
+
+use NextBus;
+use api::Partial;
+use request::{Command, Request};
+use std::io::Read;
+use xml::attribute::OwnedAttribute;
+use xml::reader::{EventReader, XmlEvent};
+
+/// Vehicle locations for an agency or route. Maps directly from
+/// Nextbus response.
+///
+/// `last_time` can be passed back as the time of the next request,
+/// so that only vehicles which changed since are returned.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct VehicleLocations {
+    vehicles: Vec<Vehicle>,
+    last_time: u64,
+}
+
+impl VehicleLocations {
+    pub fn new(vehicles: Vec<Vehicle>, last_time: u64) -> Self {
+        VehicleLocations {
+            vehicles: vehicles,
+            last_time: last_time,
+        }
+    }
+
+    pub fn vehicles(&self) -> &[Vehicle] {
+        &self.vehicles
+    }
+
+    /// Time of the response, in msec since the epoch. Pass it to
+    /// the builder's `time` to poll for changes since then.
+    pub fn last_time(&self) -> u64 {
+        self.last_time
+    }
+}
+
+impl IntoIterator for VehicleLocations {
+    type Item = Vehicle;
+    type IntoIter = ::std::vec::IntoIter<Vehicle>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.vehicles.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a VehicleLocations {
+    type Item = &'a Vehicle;
+    type IntoIter = ::std::slice::Iter<'a, Vehicle>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.vehicles.iter()
+    }
+}
+
+// Builder
+// ===============================================================
+
+pub struct VehicleLocationsBuilder<'a> {
+    client: NextBus,
+    agency: Option<&'a str>,
+    route: Option<&'a str>,
+    time: u64,
+}
+
+impl<'a> VehicleLocationsBuilder<'a> {
+    pub fn new(client: NextBus) -> Self {
+        VehicleLocationsBuilder {
+            client: client,
+            agency: None,
+            route: None,
+            time: 0,
+        }
+    }
+
+    /// Builder to set the base url for this request only,
+    /// overriding the client's.
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.client.base_url = base_url.to_owned();
+        self
+    }
+
+    /// Builder to set agency
+    pub fn agency(mut self, agency: &'a str) -> Self {
+        self.agency = Some(agency);
+        self
+    }
+
+    /// Builder to set route. If not set, gets vehicles
+    /// for all routes of the agency.
+    pub fn route(mut self, route: &'a str) -> Self {
+        self.route = Some(route);
+        self
+    }
+
+    /// Builder to set time, in msec since the epoch. Only vehicles
+    /// reported since then are returned. Use the `last_time` of the
+    /// previous response. Defaults to 0 (last 15 minutes).
+    pub fn time(mut self, time: u64) -> Self {
+        self.time = time;
+        self
+    }
+
+    pub fn get(&self) -> ::Result<VehicleLocations> {
+        self.get_partial().and_then(Partial::into_result)
+    }
+
+    /// Like get, but if the response breaks off (e.g. the body is
+    /// cut short), returns the vehicles parsed before then, with a
+    /// warning. Its last_time is then 0, so that the next request
+    /// asks for every vehicle again.
+    pub fn get_partial(&self) -> ::Result<Partial<VehicleLocations>> {
+        // Check if agency is none. If so, send error.
+        let agency = try!(self.client.agency_or_default(self.agency));
+
+        // Request. Allow one route or no route param (returns all routes)
+        let mut request = Request::new();
+        request.command(Command::VehicleLocations).agency(agency);
+        if let Some(route) = self.route {
+            request.route(route);
+        }
+        request.time(self.time);
+
+        // Parse xml into vehicle locations struct
+        self.client.get(&request, Self::from_xml_partial)
+    }
+
+    #[cfg(test)]
+    pub(super) fn from_xml<R: Read>(input: R) -> ::Result<VehicleLocations> {
+        Self::from_xml_partial(input).and_then(Partial::into_result)
+    }
+
+    fn from_xml_partial<R: Read>(input: R) -> ::Result<Partial<VehicleLocations>> {
+        // Vec for collecting vehicles
+        let mut vehicles = vec![];
+        let mut last_time: Option<u64> = None;
+        let res = Self::parse_vehicles(input, &mut vehicles, &mut last_time);
+
+        // lastTime is the last element, so a partial response has none
+        let last_time = match res {
+            Ok(()) => try!(super::required(last_time, "lastTime", "time")),
+            Err(_) => last_time.unwrap_or(0),
+        };
+        Partial::from_result(VehicleLocations {
+            vehicles: vehicles,
+            last_time: last_time,
+        }, res)
+    }
+
+    fn parse_vehicles<R: Read>(input: R,
+                               vehicles: &mut Vec<Vehicle>,
+                               last_time: &mut Option<u64>) -> ::Result<()> {
+        let mut parser = EventReader::new(input);
+
+        loop {
+            match parser.next() {
+                Ok(XmlEvent::StartElement {name, attributes, ..}) => {
+                    let name = name.borrow().local_name;
+
+                    if name == "Error" {
+                        return Err(super::parse_api_error(&mut parser, attributes));
+                    }
+
+                    if name == "vehicle" {
+                        try!(add_vehicle_to_vehicles(attributes, vehicles));
+                    } else if name == "lastTime" {
+                        for attribute in attributes {
+                            let attribute = attribute.borrow();
+                            let name = attribute.name.local_name;
+                            let value = attribute.value;
+
+                            match name {
+                                "time" =>
+                                    *last_time = Some(try!(super::parse_value(value, "lastTime", "time"))),
+                                _ => (),
+                            };
+                        }
+                    }
+                },
+                Ok(XmlEvent::EndDocument) => break,
+                Ok(_) => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Components of VehicleLocations
+// ===============================================================
+
+/// A vehicle's last reported position.
+/// Heading is in degrees, -1 if unknown.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Vehicle {
+    id: String,
+    route_tag: String,
+    dir_tag: Option<String>,
+    lat: f32,
+    lon: f32,
+    secs_since_report: usize,
+    predictable: bool,
+    heading: i32,
+    speed_km_hr: Option<f32>,
+    leading_vehicle_id: Option<String>,
+}
+
+impl Vehicle {
+    pub fn new(id: String,
+               route_tag: String,
+               dir_tag: Option<String>,
+               lat: f32,
+               lon: f32,
+               secs_since_report: usize,
+               predictable: bool,
+               heading: i32,
+               speed_km_hr: Option<f32>,
+               leading_vehicle_id: Option<String>) -> Self {
+        Vehicle {
+            id: id,
+            route_tag: route_tag,
+            dir_tag: dir_tag,
+            lat: lat,
+            lon: lon,
+            secs_since_report: secs_since_report,
+            predictable: predictable,
+            heading: heading,
+            speed_km_hr: speed_km_hr,
+            leading_vehicle_id: leading_vehicle_id,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn route_tag(&self) -> &str {
+        &self.route_tag
+    }
+
+    pub fn dir_tag(&self) -> Option<&str> {
+        self.dir_tag.as_ref().map(String::as_str)
+    }
+
+    pub fn lat(&self) -> f32 {
+        self.lat
+    }
+
+    pub fn lon(&self) -> f32 {
+        self.lon
+    }
+
+    pub fn secs_since_report(&self) -> usize {
+        self.secs_since_report
+    }
+
+    pub fn predictable(&self) -> bool {
+        self.predictable
+    }
+
+    /// Heading in degrees, -1 if unknown
+    pub fn heading(&self) -> i32 {
+        self.heading
+    }
+
+    pub fn speed_km_hr(&self) -> Option<f32> {
+        self.speed_km_hr
+    }
+
+    pub fn leading_vehicle_id(&self) -> Option<&str> {
+        self.leading_vehicle_id.as_ref().map(String::as_str)
+    }
+}
+
+// ===============================================================
+// Helpers for parsing
+// ===============================================================
+
+fn add_vehicle_to_vehicles(attributes: Vec<OwnedAttribute>,
+                           vehicles: &mut Vec<Vehicle>) -> ::Result<()> {
+    let mut id = None;
+    let mut route_tag = None;
+    let mut dir_tag = None;
+    let mut lat: Option<f32> = None;
+    let mut lon: Option<f32> = None;
+    let mut secs_since_report: Option<usize> = None;
+    let mut predictable: Option<bool> = None;
+    let mut heading: Option<i32> = None;
+    let mut speed_km_hr: Option<f32> = None;
+    let mut leading_vehicle_id = None;
+
+    for attribute in attributes {
+        let attribute = attribute.borrow();
+        let name = attribute.name.local_name;
+        let value = attribute.value;
+
+        match name {
+            "id" => id = Some(value.to_owned()),
+            "routeTag" => route_tag = Some(value.to_owned()),
+            "dirTag" => dir_tag = Some(value.to_owned()),
+            "lat" => lat = Some(try!(super::parse_value(value, "vehicle", "lat"))),
+            "lon" => lon = Some(try!(super::parse_value(value, "vehicle", "lon"))),
+            "secsSinceReport" =>
+                secs_since_report = Some(try!(super::parse_value(value, "vehicle", "secsSinceReport"))),
+            "predictable" =>
+                predictable = Some(try!(super::parse_value(value, "vehicle", "predictable"))),
+            "heading" => heading = Some(try!(super::parse_value(value, "vehicle", "heading"))),
+            "speedKmHr" =>
+                speed_km_hr = Some(try!(super::parse_value(value, "vehicle", "speedKmHr"))),
+            "leadingVehicleId" => leading_vehicle_id = Some(value.to_owned()),
+            _ => (),
+        }
+    }
+    vehicles.push(Vehicle {
+        id: try!(super::required(id, "vehicle", "id")),
+        route_tag: try!(super::required(route_tag, "vehicle", "routeTag")),
+        dir_tag: dir_tag,
+        lat: try!(super::required(lat, "vehicle", "lat")),
+        lon: try!(super::required(lon, "vehicle", "lon")),
+        secs_since_report: try!(super::required(secs_since_report, "vehicle", "secsSinceReport")),
+        predictable: try!(super::required(predictable, "vehicle", "predictable")),
+        heading: try!(super::required(heading, "vehicle", "heading")),
+        speed_km_hr: speed_km_hr,
+        leading_vehicle_id: leading_vehicle_id,
+    });
+    Ok(())
+}
+
+// Tests
+// ===============================================================
+
+#[cfg(test)]
+mod test {
+    use api::test::assert_invalid_attributes;
+    use std::io::Cursor;
+    use transport::fixture_client;
+    use super::*;
+
+    const GOOD_VEHICLE_LOCATIONS_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright San Francisco Muni 2016.\">
+        <vehicle id=\"1453\" routeTag=\"N\" dirTag=\"N____O_F00\" lat=\"37.7664\"
+            lon=\"-122.4489\" secsSinceReport=\"29\" predictable=\"true\"
+            heading=\"276\" speedKmHr=\"12.5\" leadingVehicleId=\"1112\"/>
+        <vehicle id=\"1460\" routeTag=\"N\" lat=\"37.7651\" lon=\"-122.4577\"
+            secsSinceReport=\"5\" predictable=\"false\" heading=\"-1\"/>
+        <lastTime time=\"1144953500233\"/>
+        </body>";
+
+    const MISSING_LAST_TIME_VEHICLE_LOCATIONS_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright San Francisco Muni 2016.\">
+        <vehicle id=\"1460\" routeTag=\"N\" lat=\"37.7651\" lon=\"-122.4577\"
+            secsSinceReport=\"5\" predictable=\"false\" heading=\"-1\"/>
+        </body>";
+
+    #[test]
+    fn parse_good_xml() {
+        let buffer = Cursor::new(GOOD_VEHICLE_LOCATIONS_XML);
+        let locations = VehicleLocationsBuilder::from_xml(buffer).unwrap();
+
+        let test_1453 = Vehicle::new("1453".to_owned(),
+                                     "N".to_owned(),
+                                     Some("N____O_F00".to_owned()),
+                                     37.7664,
+                                     -122.4489,
+                                     29,
+                                     true,
+                                     276,
+                                     Some(12.5),
+                                     Some("1112".to_owned()));
+        let test_1460 = Vehicle::new("1460".to_owned(),
+                                     "N".to_owned(),
+                                     None,
+                                     37.7651,
+                                     -122.4577,
+                                     5,
+                                     false,
+                                     -1,
+                                     None,
+                                     None);
+        let test_locations = VehicleLocations::new(vec![test_1453, test_1460],
+                                                   1144953500233);
+
+        assert_eq!(locations, test_locations);
+    }
+
+    #[test]
+    #[should_panic]
+    fn parse_bad_xml_missing_last_time() {
+        let buffer = Cursor::new(MISSING_LAST_TIME_VEHICLE_LOCATIONS_XML);
+        VehicleLocationsBuilder::from_xml(buffer).unwrap();
+    }
+
+    #[test]
+    fn parse_invalid_attribute() {
+        // Corrupted value in the response, and where it should fail
+        let cases = vec![
+            ("lat=\"37.7664\"", "lat=\"N37.7664\"", "vehicle", "lat"),
+            ("lon=\"-122.4489\"", "lon=\"\"", "vehicle", "lon"),
+            ("secsSinceReport=\"29\"", "secsSinceReport=\"29s\"", "vehicle", "secsSinceReport"),
+            ("predictable=\"true\"", "predictable=\"1\"", "vehicle", "predictable"),
+            ("heading=\"276\"", "heading=\"NW\"", "vehicle", "heading"),
+            ("speedKmHr=\"12.5\"", "speedKmHr=\"fast\"", "vehicle", "speedKmHr"),
+            ("time=\"1144953500233\"", "time=\"yesterday\"", "lastTime", "time"),
+        ];
+        assert_invalid_attributes(GOOD_VEHICLE_LOCATIONS_XML, cases,
+                                  VehicleLocationsBuilder::from_xml);
+    }
+
+    #[test]
+    fn should_get_vehicle_locations() {
+        let locations = VehicleLocationsBuilder::new(fixture_client())
+            .agency("sf-muni")
+            .route("N")
+            .get()
+            .unwrap();
+        let last_time = locations.last_time;
+        assert_eq!(locations.vehicles().len(), 2);
+
+        // Only vehicles which changed since the first call
+        let locations = VehicleLocationsBuilder::new(fixture_client())
+            .agency("sf-muni")
+            .route("N")
+            .time(last_time)
+            .get()
+            .unwrap();
+        assert_eq!(locations.vehicles().len(), 1);
+        assert!(locations.last_time > last_time);
+    }
+}
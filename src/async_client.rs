@@ -0,0 +1,300 @@
+//! Module for the async client
+//!
+//! AsyncNextBus wraps a NextBus client and a thread pool. Its
+//! builders take the same parameters as the blocking ones, but
+//! `get()` runs the request on the pool and returns a future.
+//!
+//! The blocking builders do the work, so urls are built and
+//! responses parsed exactly the same way. Only enabled with the
+//! `async` feature.
+
+use api::{Set, Unset};
+use api::agency_list::AgencyList;
+use api::predictions::{Predictions, PredictionsRequest};
+use api::route_config::{RouteConfig, RouteConfigRequest};
+use api::route_list::{RouteList, RouteListRequest};
+use error::Error;
+use futures_cpupool::{CpuFuture, CpuPool};
+use NextBus;
+
+/// Async client for the NextBus API. Requests are sent through the
+/// wrapped client (and its transport), on a thread pool.
+///
+/// Cloning is cheap, and clones share the same pool.
+#[derive(Clone)]
+pub struct AsyncNextBus {
+    client: NextBus,
+    pool: CpuPool,
+}
+
+impl AsyncNextBus {
+    /// Async client with a pool of `threads` threads. Each request
+    /// holds a thread while it waits on NextBus, so size the pool for
+    /// the requests to have in flight at once (e.g. the stops polled
+    /// together), not for the number of cpus.
+    pub fn new(client: NextBus, threads: usize) -> Self {
+        AsyncNextBus::with_pool(client, CpuPool::new(threads))
+    }
+
+    /// Async client which runs requests on an existing pool.
+    pub fn with_pool(client: NextBus, pool: CpuPool) -> Self {
+        AsyncNextBus {
+            client: client,
+            pool: pool,
+        }
+    }
+
+    pub fn client(&self) -> &NextBus {
+        &self.client
+    }
+
+    // Builders for each command
+    // ===============================================================
+
+    pub fn agency_list(&self) -> AsyncAgencyListBuilder {
+        AsyncAgencyListBuilder {
+            client: self.client.clone(),
+            pool: self.pool.clone(),
+        }
+    }
+
+    pub fn route_list(&self) -> AsyncRouteListBuilder {
+        AsyncRouteListBuilder {
+            client: self.client.clone(),
+            pool: self.pool.clone(),
+            request: RouteListRequest::new(),
+        }
+    }
+
+    pub fn route_config(&self) -> AsyncRouteConfigBuilder {
+        AsyncRouteConfigBuilder {
+            client: self.client.clone(),
+            pool: self.pool.clone(),
+            request: RouteConfigRequest::new(),
+        }
+    }
+
+    pub fn predictions(&self) -> AsyncPredictionsBuilder {
+        AsyncPredictionsBuilder {
+            client: self.client.clone(),
+            pool: self.pool.clone(),
+            agency: None,
+            route: Unset,
+            stop: Unset,
+            use_short_titles: false,
+        }
+    }
+}
+
+// Builders
+// ===============================================================
+//
+// These own their parameters, since the request outlives the
+// builder once it's on the pool.
+
+pub struct AsyncAgencyListBuilder {
+    client: NextBus,
+    pool: CpuPool,
+}
+
+impl AsyncAgencyListBuilder {
+    pub fn get(&self) -> CpuFuture<AgencyList, Error> {
+        let client = self.client.clone();
+        self.pool.spawn_fn(move || client.agency_list().get())
+    }
+}
+
+pub struct AsyncRouteListBuilder {
+    client: NextBus,
+    pool: CpuPool,
+    request: RouteListRequest,
+}
+
+impl AsyncRouteListBuilder {
+    /// Builder to set agency
+    pub fn agency(mut self, agency: &str) -> Self {
+        self.request.agency(agency);
+        self
+    }
+
+    /// Builder to have NextBus send short titles in place of
+    /// titles, where there are any.
+    pub fn use_short_titles(mut self) -> Self {
+        self.request.use_short_titles();
+        self
+    }
+
+    pub fn get(&self) -> CpuFuture<RouteList, Error> {
+        let client = self.client.clone();
+        let request = self.request.clone();
+        self.pool.spawn_fn(move || request.get(&client))
+    }
+}
+
+pub struct AsyncRouteConfigBuilder {
+    client: NextBus,
+    pool: CpuPool,
+    request: RouteConfigRequest,
+}
+
+impl AsyncRouteConfigBuilder {
+    /// Builder to set agency
+    pub fn agency(mut self, agency: &str) -> Self {
+        self.request.agency(agency);
+        self
+    }
+
+    /// Builder to set route. If not set, gets all routes.
+    pub fn route(mut self, route: &str) -> Self {
+        self.request.route(route);
+        self
+    }
+
+    /// Builder to leave out paths
+    pub fn terse(mut self) -> Self {
+        self.request.terse();
+        self
+    }
+
+    /// Builder to have NextBus send short titles in place of
+    /// titles, where there are any.
+    pub fn use_short_titles(mut self) -> Self {
+        self.request.use_short_titles();
+        self
+    }
+
+    pub fn get(&self) -> CpuFuture<RouteConfig, Error> {
+        let client = self.client.clone();
+        let request = self.request.clone();
+        self.pool.spawn_fn(move || request.get(&client))
+    }
+}
+
+/// Like PredictionsBuilder, `get` is only there once the route and
+/// stop are set.
+pub struct AsyncPredictionsBuilder<R = Unset, S = Unset> {
+    client: NextBus,
+    pool: CpuPool,
+    agency: Option<String>,
+    route: R,
+    stop: S,
+    use_short_titles: bool,
+}
+
+impl<R, S> AsyncPredictionsBuilder<R, S> {
+    /// Builder to set agency
+    pub fn agency(mut self, agency: &str) -> Self {
+        self.agency = Some(agency.to_owned());
+        self
+    }
+
+    /// Builder to have NextBus send short titles in place of
+    /// titles, where there are any.
+    pub fn use_short_titles(mut self) -> Self {
+        self.use_short_titles = true;
+        self
+    }
+
+    /// Builder to set route
+    pub fn route(self, route: &str) -> AsyncPredictionsBuilder<Set<String>, S> {
+        AsyncPredictionsBuilder {
+            client: self.client,
+            pool: self.pool,
+            agency: self.agency,
+            route: Set(route.to_owned()),
+            stop: self.stop,
+            use_short_titles: self.use_short_titles,
+        }
+    }
+
+    /// Builder to set stop
+    pub fn stop(self, stop: &str) -> AsyncPredictionsBuilder<R, Set<String>> {
+        AsyncPredictionsBuilder {
+            client: self.client,
+            pool: self.pool,
+            agency: self.agency,
+            route: self.route,
+            stop: Set(stop.to_owned()),
+            use_short_titles: self.use_short_titles,
+        }
+    }
+}
+
+impl AsyncPredictionsBuilder<Set<String>, Set<String>> {
+    pub fn get(&self) -> CpuFuture<Predictions, Error> {
+        let client = self.client.clone();
+        let mut request = PredictionsRequest::new(&self.route.0, &self.stop.0);
+        if let Some(ref agency) = self.agency {
+            request.agency(agency);
+        }
+        if self.use_short_titles {
+            request.use_short_titles();
+        }
+        self.pool.spawn_fn(move || request.get(&client))
+    }
+}
+
+// Tests
+// ===============================================================
+
+#[cfg(test)]
+mod test {
+    use futures::future::{self, Future};
+    use transport::FixtureTransport;
+    use NextBus;
+    use super::*;
+
+    const NEXTBUS_URL: &'static str = "http://webservices.nextbus.com/service/publicXMLFeed";
+
+    const ROUTE_LIST_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright Massachusetts Institute of Technology 2016.\">
+        <route tag=\"boston\" title=\"Boston Daytime\"/>
+        </body>";
+
+    const PREDICTIONS_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright MBTA 2016.\">
+        <predictions agencyTitle=\"MBTA\" routeTag=\"1\" routeTitle=\"1\"
+            stopTitle=\"Massachusetts Ave @ Holyoke St\" stopTag=\"110\">
+        <direction title=\"Dudley Station via Mass. Ave.\">
+        <prediction epochTime=\"1465430935426\" seconds=\"248\" minutes=\"4\"
+            isDeparture=\"false\" dirTag=\"1_1_var0\" vehicle=\"0634\" block=\"C01_5\"/>
+        </direction>
+        </predictions>
+        </body>";
+
+    fn client() -> AsyncNextBus {
+        let mut fixtures = FixtureTransport::new();
+        fixtures.add(&format!("{}?command=routeList&a=mit", NEXTBUS_URL), ROUTE_LIST_XML);
+        fixtures.add(&format!("{}?command=predictions&a=mbta&r=1&s=110", NEXTBUS_URL),
+                     PREDICTIONS_XML);
+        AsyncNextBus::new(NextBus::new().with_transport(fixtures), 2)
+    }
+
+    #[test]
+    fn get_route_list() {
+        let routes = client().route_list().agency("mit").get().wait().unwrap();
+        assert_eq!(routes.into_iter().count(), 1);
+    }
+
+    #[test]
+    fn get_predictions_concurrently() {
+        let client = client();
+        let requests: Vec<_> = (0..10)
+            .map(|_| client.predictions().agency("mbta").route("1").stop("110").get())
+            .collect();
+
+        let all_predictions = future::join_all(requests).wait().unwrap();
+        assert_eq!(all_predictions.len(), 10);
+        for predictions in all_predictions {
+            assert_eq!(predictions.stop_tag(), "110");
+        }
+    }
+
+    #[test]
+    fn get_missing_param() {
+        // Errors from the blocking builder resolve the future
+        assert!(client().predictions().route("1").stop("110").get().wait().is_err());
+    }
+}
@@ -0,0 +1,35 @@
+//! Mock NextBus server, for testing apps against localhost
+//!
+//! Serves the publicXMLFeed endpoint from a fixtures directory,
+//! named as recorded by RecordTransport.
+//!
+//! Usage: nextbus-mock-server [FIXTURES_DIR] [ADDRESS]
+//!
+//! Defaults to `fixtures` and `127.0.0.1:8080`.
+
+extern crate nextbus;
+
+use nextbus::MockServer;
+use std::env;
+use std::process;
+use std::thread;
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let dir = args.next().unwrap_or("fixtures".to_owned());
+    let addr = args.next().unwrap_or("127.0.0.1:8080".to_owned());
+
+    let listening = match MockServer::new(&dir).listen(&addr[..]) {
+        Ok(listening) => listening,
+        Err(err) => {
+            eprintln!("Could not listen on {}: {}", addr, err);
+            process::exit(1);
+        },
+    };
+    println!("Serving {} at {}", dir, listening.base_url());
+
+    // Requests are answered on the server's threads
+    loop {
+        thread::park();
+    }
+}
@@ -0,0 +1,428 @@
+//! Module for caching responses
+//!
+//! A Cache stores response bodies by the url of their request, and
+//! serves them again until their command's TTL runs out. Commands
+//! with no TTL aren't cached.
+//!
+//! Only responses which parse are stored, so a NextBus error is
+//! never served from the cache.
+//!
+//! - MemoryStore: for the life of the process
+//! - DiskStore: one file per response in a directory, so that it
+//!   lasts between runs
+
+use clock::Clock;
+use error::Error;
+use hyper::Url;
+use request::Command;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A response body, and when it was stored
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachedResponse {
+    body: Vec<u8>,
+    stored_at: SystemTime,
+}
+
+impl CachedResponse {
+    pub fn new(body: Vec<u8>, stored_at: SystemTime) -> Self {
+        CachedResponse {
+            body: body,
+            stored_at: stored_at,
+        }
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn stored_at(&self) -> SystemTime {
+        self.stored_at
+    }
+}
+
+/// Stores responses by key. Failing to store is not an error, the
+/// response just won't be cached.
+pub trait CacheStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<CachedResponse>;
+    fn put(&self, key: &str, response: CachedResponse);
+    fn remove(&self, key: &str);
+    fn keys(&self) -> Vec<String>;
+}
+
+// Cache
+// ===============================================================
+
+/// Responses by url, with a TTL per command.
+///
+/// By default, the commands which rarely change are cached for a
+/// day (agencyList, routeList, routeConfig, schedule), and the rest
+/// aren't cached.
+#[derive(Clone)]
+pub struct Cache {
+    store: Arc<CacheStore>,
+    ttls: HashMap<Command, Duration>,
+}
+
+impl Cache {
+    pub fn new<S: CacheStore + 'static>(store: S) -> Self {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let mut ttls = HashMap::new();
+        ttls.insert(Command::AgencyList, day);
+        ttls.insert(Command::RouteList, day);
+        ttls.insert(Command::RouteConfig, day);
+        ttls.insert(Command::Schedule, day);
+
+        Cache {
+            store: Arc::new(store),
+            ttls: ttls,
+        }
+    }
+
+    /// Cache in memory
+    pub fn memory() -> Self {
+        Cache::new(MemoryStore::new())
+    }
+
+    /// Cache on disk, in the directory (created if missing)
+    pub fn disk<P: AsRef<Path>>(dir: P) -> ::Result<Self> {
+        Ok(Cache::new(try!(DiskStore::new(dir))))
+    }
+
+    /// Set how long responses for a command are served from the cache.
+    pub fn with_ttl(mut self, command: Command, ttl: Duration) -> Self {
+        self.ttls.insert(command, ttl);
+        self
+    }
+
+    /// Stop caching responses for a command.
+    pub fn without_ttl(mut self, command: Command) -> Self {
+        self.ttls.remove(&command);
+        self
+    }
+
+    pub fn ttl(&self, command: Command) -> Option<Duration> {
+        self.ttls.get(&command).cloned()
+    }
+
+    /// Body for the url, if it's stored and its TTL hasn't run out.
+    pub fn get(&self, command: Command, url: &str, clock: &Clock) -> Option<Vec<u8>> {
+        let ttl = match self.ttl(command) {
+            Some(ttl) => ttl,
+            None => return None,
+        };
+
+        self.store.get(&cache_key(url)).and_then(|response| {
+            match clock.system_time().duration_since(response.stored_at) {
+                Ok(age) if age < ttl => Some(response.body),
+                _ => None,
+            }
+        })
+    }
+
+    /// Store the body for the url, if the command is cached.
+    pub fn put(&self, command: Command, url: &str, body: Vec<u8>, clock: &Clock) {
+        if self.ttl(command).is_some() {
+            self.store.put(&cache_key(url), CachedResponse::new(body, clock.system_time()));
+        }
+    }
+
+    /// Remove the response for a url
+    pub fn invalidate(&self, url: &str) {
+        self.store.remove(&cache_key(url));
+    }
+
+    /// Remove all responses for a command
+    pub fn invalidate_command(&self, command: Command) {
+        let command = command.to_string();
+        for key in self.store.keys() {
+            let is_command = Url::parse(&key).ok()
+                .and_then(|url| url.query_pairs())
+                .map_or(false, |pairs| pairs.iter().any(|&(ref name, ref value)| {
+                    name == "command" && *value == command
+                }));
+            if is_command {
+                self.store.remove(&key);
+            }
+        }
+    }
+
+    /// Remove all responses
+    pub fn clear(&self) {
+        for key in self.store.keys() {
+            self.store.remove(&key);
+        }
+    }
+}
+
+// Urls are keyed the way they serialize once built, so that
+// the same url always matches regardless of how it was written.
+fn cache_key(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(url) => url.to_string(),
+        Err(_) => url.to_owned(),
+    }
+}
+
+// Memory
+// ===============================================================
+
+pub struct MemoryStore {
+    responses: Mutex<HashMap<String, CachedResponse>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore {
+            responses: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl CacheStore for MemoryStore {
+    fn get(&self, key: &str) -> Option<CachedResponse> {
+        self.responses.lock().unwrap().get(key).cloned()
+    }
+
+    fn put(&self, key: &str, response: CachedResponse) {
+        self.responses.lock().unwrap().insert(key.to_owned(), response);
+    }
+
+    fn remove(&self, key: &str) {
+        self.responses.lock().unwrap().remove(key);
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.responses.lock().unwrap().keys().cloned().collect()
+    }
+}
+
+// Disk
+// ===============================================================
+
+/// Stores each response in its own file, named by a hash of its key.
+/// The file has the time it was stored (secs since the epoch) on the
+/// first line, the key on the second, then the body.
+///
+/// The hash is FNV-1a, which unlike std's hasher won't change between
+/// Rust releases, so the files outlast a toolchain upgrade.
+pub struct DiskStore {
+    dir: PathBuf,
+}
+
+impl DiskStore {
+    /// Store in the directory, creating it if missing.
+    pub fn new<P: AsRef<Path>>(dir: P) -> ::Result<Self> {
+        try!(fs::create_dir_all(dir.as_ref()));
+        Ok(DiskStore {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.cache", fnv1a(key.as_bytes())))
+    }
+
+    // Returns the key and response stored in a file
+    fn read(path: &Path) -> ::Result<(String, CachedResponse)> {
+        let mut file = BufReader::new(try!(File::open(path)));
+
+        let mut stored_at = String::new();
+        try!(file.read_line(&mut stored_at));
+        let stored_at: u64 = try!(stored_at.trim().parse()
+            .map_err(|_| Error::CorruptFile(path.to_path_buf())));
+
+        let mut key = String::new();
+        try!(file.read_line(&mut key));
+
+        let mut body = Vec::new();
+        try!(file.read_to_end(&mut body));
+
+        let stored_at = UNIX_EPOCH + Duration::from_secs(stored_at);
+        Ok((key.trim_right().to_owned(), CachedResponse::new(body, stored_at)))
+    }
+
+    fn write(&self, key: &str, response: &CachedResponse) -> ::Result<()> {
+        let stored_at = response.stored_at.duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or(0);
+
+        // Written next to the entry then moved over it, so that a
+        // reader never sees one half written.
+        let path = self.path(key);
+        let tmp_path = path.with_extension("cache.tmp");
+        {
+            let mut file = try!(File::create(&tmp_path));
+            try!(write!(file, "{}\n{}\n", stored_at, key));
+            try!(file.write_all(&response.body));
+        }
+        Ok(try!(fs::rename(tmp_path, path)))
+    }
+}
+
+// 64-bit FNV-1a
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl CacheStore for DiskStore {
+    fn get(&self, key: &str) -> Option<CachedResponse> {
+        match DiskStore::read(&self.path(key)) {
+            // Guard against a hash collision
+            Ok((stored_key, response)) if stored_key == key => Some(response),
+            _ => None,
+        }
+    }
+
+    fn put(&self, key: &str, response: CachedResponse) {
+        let _ = self.write(key, &response);
+    }
+
+    fn remove(&self, key: &str) {
+        let _ = fs::remove_file(self.path(key));
+    }
+
+    fn keys(&self) -> Vec<String> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == "cache"))
+            .filter_map(|path| DiskStore::read(&path).ok())
+            .map(|(key, _)| key)
+            .collect()
+    }
+}
+
+// Tests
+// ===============================================================
+
+#[cfg(test)]
+mod test {
+    use clock::FakeClock;
+    use request::Command;
+    use std::env;
+    use std::fs;
+    use std::time::Duration;
+    use super::*;
+
+    const ROUTE_CONFIG_URL: &'static str = "http://webservices.nextbus.com/service/publicXMLFeed\
+                                            ?command=routeConfig&a=mit";
+    const ROUTE_LIST_URL: &'static str = "http://webservices.nextbus.com/service/publicXMLFeed\
+                                          ?command=routeList&a=mit";
+    const PREDICTIONS_URL: &'static str = "http://webservices.nextbus.com/service/publicXMLFeed\
+                                           ?command=predictions&a=mit&r=boston&s=massave";
+
+    fn expires(cache: Cache, clock: &FakeClock) {
+        let cache = cache.with_ttl(Command::Predictions, Duration::from_secs(10));
+
+        cache.put(Command::RouteConfig, ROUTE_CONFIG_URL, b"<body/>".to_vec(), clock);
+        cache.put(Command::Predictions, PREDICTIONS_URL, b"<body/>".to_vec(), clock);
+        assert_eq!(cache.get(Command::RouteConfig, ROUTE_CONFIG_URL, clock),
+                   Some(b"<body/>".to_vec()));
+        assert!(cache.get(Command::Predictions, PREDICTIONS_URL, clock).is_some());
+
+        // Predictions expire after seconds, route config after a day
+        clock.advance(Duration::from_secs(10));
+        assert!(cache.get(Command::Predictions, PREDICTIONS_URL, clock).is_none());
+        assert!(cache.get(Command::RouteConfig, ROUTE_CONFIG_URL, clock).is_some());
+        clock.advance(Duration::from_secs(24 * 60 * 60));
+        assert!(cache.get(Command::RouteConfig, ROUTE_CONFIG_URL, clock).is_none());
+    }
+
+    fn invalidates(cache: Cache, clock: &FakeClock) {
+        cache.put(Command::RouteConfig, ROUTE_CONFIG_URL, b"<body/>".to_vec(), clock);
+        cache.put(Command::RouteList, ROUTE_LIST_URL, b"<body/>".to_vec(), clock);
+
+        cache.invalidate_command(Command::RouteConfig);
+        assert!(cache.get(Command::RouteConfig, ROUTE_CONFIG_URL, clock).is_none());
+        assert!(cache.get(Command::RouteList, ROUTE_LIST_URL, clock).is_some());
+
+        cache.put(Command::RouteConfig, ROUTE_CONFIG_URL, b"<body/>".to_vec(), clock);
+        cache.invalidate(ROUTE_CONFIG_URL);
+        assert!(cache.get(Command::RouteConfig, ROUTE_CONFIG_URL, clock).is_none());
+
+        cache.clear();
+        assert!(cache.get(Command::RouteList, ROUTE_LIST_URL, clock).is_none());
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("nextbus-cache-{}-{}", name, ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn memory_expires() {
+        expires(Cache::memory(), &FakeClock::new());
+    }
+
+    #[test]
+    fn memory_invalidates() {
+        invalidates(Cache::memory(), &FakeClock::new());
+    }
+
+    #[test]
+    fn memory_uncached_command() {
+        let clock = FakeClock::new();
+        let cache = Cache::memory();
+        cache.put(Command::Predictions, PREDICTIONS_URL, b"<body/>".to_vec(), &clock);
+        assert!(cache.get(Command::Predictions, PREDICTIONS_URL, &clock).is_none());
+    }
+
+    #[test]
+    fn disk_expires() {
+        let dir = temp_dir("expires");
+        expires(Cache::disk(&dir).unwrap(), &FakeClock::new());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn disk_invalidates() {
+        let dir = temp_dir("invalidates");
+        invalidates(Cache::disk(&dir).unwrap(), &FakeClock::new());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn disk_lasts_between_caches() {
+        let dir = temp_dir("lasts");
+        let clock = FakeClock::new();
+        Cache::disk(&dir).unwrap()
+            .put(Command::RouteConfig, ROUTE_CONFIG_URL, b"<body/>".to_vec(), &clock);
+
+        let cache = Cache::disk(&dir).unwrap();
+        assert_eq!(cache.get(Command::RouteConfig, ROUTE_CONFIG_URL, &clock),
+                   Some(b"<body/>".to_vec()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn disk_file_names_are_stable() {
+        let dir = temp_dir("names");
+        let store = DiskStore::new(&dir).unwrap();
+        assert_eq!(store.path("routeConfig"), dir.join("043a78a0a5fd7228.cache"));
+
+        // Nothing is left beside the entry once it's written
+        store.put("routeConfig", CachedResponse::new(b"<body/>".to_vec(), UNIX_EPOCH));
+        let names: Vec<_> = fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec!["043a78a0a5fd7228.cache"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+//! Module for telling and waiting on time
+//!
+//! The client waits through a Clock (e.g. between retries), so that
+//! time can be faked in tests instead of actually sleeping.
+//!
+//! - SystemClock: the real time, sleeps the thread
+//! - FakeClock: only moves when slept or advanced
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Tells the time, and waits
+pub trait Clock: Send + Sync {
+    /// Monotonic time, for measuring
+    fn now(&self) -> Instant;
+    /// Calendar time, for storing
+    fn system_time(&self) -> SystemTime;
+    fn sleep(&self, duration: Duration);
+}
+
+// System
+// ===============================================================
+
+/// Default clock. Sleeping blocks the thread.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+// Fake
+// ===============================================================
+
+/// Clock which starts at its creation, and only moves when slept or
+/// advanced. Sleeping returns immediately, and is recorded.
+///
+/// Clones share the same time, so one can be given to a client and
+/// another kept to check on the sleeps.
+#[derive(Clone)]
+pub struct FakeClock {
+    start: Instant,
+    start_system_time: SystemTime,
+    elapsed: Arc<Mutex<Duration>>,
+    sleeps: Arc<Mutex<Vec<Duration>>>,
+}
+
+impl FakeClock {
+    pub fn new() -> Self {
+        FakeClock {
+            start: Instant::now(),
+            start_system_time: SystemTime::now(),
+            elapsed: Arc::new(Mutex::new(Duration::from_secs(0))),
+            sleeps: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Move the time forward, without recording a sleep
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    /// Time moved since creation
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+
+    /// Every sleep so far, in order
+    pub fn sleeps(&self) -> Vec<Duration> {
+        self.sleeps.lock().unwrap().clone()
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.start_system_time + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.sleeps.lock().unwrap().push(duration);
+        self.advance(duration);
+    }
+}
+
+// Tests
+// ===============================================================
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use super::*;
+
+    #[test]
+    fn fake_clock_moves_on_sleep() {
+        let clock = FakeClock::new();
+        let start = clock.now();
+        let start_system_time = clock.system_time();
+
+        clock.sleep(Duration::from_millis(100));
+        clock.clone().advance(Duration::from_millis(50));
+        clock.sleep(Duration::from_millis(200));
+
+        assert_eq!(clock.now() - start, Duration::from_millis(350));
+        assert_eq!(clock.system_time().duration_since(start_system_time).unwrap(),
+                   Duration::from_millis(350));
+        assert_eq!(clock.sleeps(), vec![Duration::from_millis(100),
+                                        Duration::from_millis(200)]);
+    }
+}
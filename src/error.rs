@@ -2,7 +2,13 @@
 
 use std::error;
 use std::fmt;
+use std::io;
 use hyper::error::Error as HyperError;
+use request::Command;
+use std::path::PathBuf;
+use std::time::Duration;
+use xml::common::{Position, TextPosition};
+use xml::reader::Error as XmlError;
 
 
 pub type Result<T> = ::std::result::Result<T, Error>;
@@ -10,19 +16,92 @@ pub type Result<T> = ::std::result::Result<T, Error>;
 
 #[derive(Debug)]
 pub enum Error {
-    BuildCommandError,
+    /// Error returned by the NextBus API in place of a response,
+    /// e.g. for an invalid agency or exceeded bandwidth limits.
+    /// `should_retry` is set when the request may succeed later.
+    ApiError { message: String, should_retry: bool },
     BuildUrlError,
+    /// A local file (e.g. a cache entry or route config snapshot)
+    /// which couldn't be read back.
+    CorruptFile(PathBuf),
     HttpError(HyperError),
-    ParseError,
+    /// A command name which isn't one of the NextBus commands.
+    InvalidCommand(String),
+    /// An attribute of an element in the response had a value
+    /// which couldn't be parsed, e.g. `lat="north"`.
+    InvalidAttribute { element: &'static str, attribute: &'static str, value: String },
+    /// Error reading a response's body, or reading or writing local
+    /// files, e.g. for a disk cache.
+    IoError(io::Error),
+    /// An element in the response was missing a required attribute.
+    MissingAttribute { element: &'static str, attribute: &'static str },
+    /// The response was missing a required element, e.g. a
+    /// predictions response without `<predictions>`.
+    MissingElement(&'static str),
+    /// A builder was missing a parameter its command requires,
+    /// e.g. the route for predictions.
+    MissingParameter(&'static str),
+    /// The client's rate limit was reached, and rejects requests
+    /// over it. `wait` is how long until the request would be let
+    /// through, or the window for a limit of zero, which never lets
+    /// one through.
+    RateLimitError { wait: Duration },
+    /// A request had more than one value for a parameter its
+    /// command only takes once, e.g. two stops for predictions.
+    RepeatedParameter { command: Command, param: &'static str },
+    /// A request had a parameter its command doesn't take, e.g. a
+    /// route for agencyList.
+    UnexpectedParameter { command: Command, param: &'static str },
+    /// The response wasn't well formed XML. `position` is where
+    /// in the response the reader stopped.
+    Xml { position: TextPosition, source: XmlError },
+}
+
+impl Error {
+    /// Whether the request may succeed if sent again: on a failure
+    /// to connect or read the response (including a body cut short,
+    /// which breaks its XML), or when NextBus says so. Not for errors
+    /// in the request itself, like a bad agency tag.
+    pub fn should_retry(&self) -> bool {
+        match *self {
+            Error::ApiError { should_retry, .. } => should_retry,
+            Error::HttpError(_) => true,
+            Error::IoError(_) => true,
+            Error::Xml { .. } => true,
+            _ => false,
+        }
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Error::BuildCommandError => write!(f, "Error Building Command"),
+            Error::ApiError { ref message, .. } => write!(f, "NextBus API Error: {}", message),
             Error::BuildUrlError => write!(f, "Error Building Url"),
+            Error::CorruptFile(ref path) => write!(f, "Corrupt File: {}", path.display()),
             Error::HttpError(ref err) => write!(f, "HTTP Error: {}", err),
-            Error::ParseError => write!(f, "Error Parsing XML"),
+            Error::InvalidCommand(ref command) => write!(f, "Invalid Command: {}", command),
+            Error::InvalidAttribute { element, attribute, ref value } => {
+                write!(f, "Invalid Attribute: {}=\"{}\" on <{}>", attribute, value, element)
+            },
+            Error::IoError(ref err) => write!(f, "IO Error: {}", err),
+            Error::MissingAttribute { element, attribute } => {
+                write!(f, "Missing Attribute: {} on <{}>", attribute, element)
+            },
+            Error::MissingElement(element) => write!(f, "Missing Element: <{}>", element),
+            Error::MissingParameter(param) => write!(f, "Missing Parameter: {}", param),
+            Error::RateLimitError { ref wait } => {
+                write!(f, "Rate Limit Reached: retry in {:?}", wait)
+            },
+            Error::RepeatedParameter { command, param } => {
+                write!(f, "Repeated Parameter: {} for {}", param, command)
+            },
+            Error::UnexpectedParameter { command, param } => {
+                write!(f, "Unexpected Parameter: {} for {}", param, command)
+            },
+            Error::Xml { ref position, ref source } => {
+                write!(f, "XML Error at {}: {}", position, source)
+            },
         }
     }
 }
@@ -30,16 +109,28 @@ impl fmt::Display for Error {
 impl error::Error for Error {
     fn description(&self) -> &str {
         match *self {
-            Error::BuildCommandError => "Error Building Command",
+            Error::ApiError { ref message, .. } => message,
             Error::BuildUrlError => "Error Building Url",
+            Error::CorruptFile(_) => "Corrupt File",
             Error::HttpError(ref err) => err.description(),
-            Error::ParseError => "Error Parsing XML",
+            Error::InvalidCommand(_) => "Invalid Command",
+            Error::InvalidAttribute { .. } => "Invalid Attribute",
+            Error::IoError(ref err) => err.description(),
+            Error::MissingAttribute { .. } => "Missing Attribute",
+            Error::MissingElement(_) => "Missing Element",
+            Error::MissingParameter(_) => "Missing Parameter",
+            Error::RateLimitError { .. } => "Rate Limit Reached",
+            Error::RepeatedParameter { .. } => "Repeated Parameter",
+            Error::UnexpectedParameter { .. } => "Unexpected Parameter",
+            Error::Xml { ref source, .. } => source.description(),
         }
     }
 
     fn cause(&self) -> Option<&error::Error> {
         match *self {
             Error::HttpError(ref err) => Some(err),
+            Error::IoError(ref err) => Some(err),
+            Error::Xml { ref source, .. } => Some(source),
             _ => None,
         }
     }
@@ -51,6 +142,17 @@ impl From<HyperError> for Error {
     }
 }
 
-// Create an enum just for builder errors, to get more granular errors.
-// Then pass these into the normal error! Maybe do this later
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::IoError(err)
+    }
+}
 
+impl From<XmlError> for Error {
+    fn from(err: XmlError) -> Error {
+        Error::Xml {
+            position: err.position(),
+            source: err,
+        }
+    }
+}
@@ -1,7 +1,4 @@
 #![allow(dead_code)]
-// TODO: Definitely take out verfication from url builder. It no longer needs it,
-// since building is already constrained by individual builders.
-//
 // TODO: Make decisions about below after building routeConfig.
 //
 // TODO: Maybe move http request out of request, turn it into just a url builder.
@@ -36,62 +33,749 @@
 //! - AgencyList
 //! - RouteList
 //! - RouteConfig
-//! - Predictions
+//! - Predictions (by route and stop tag, or by stop id)
 //! - PredicionsForMultiStops
 //! - Schedule
 //! - Messages
 //! - VehicleLocations
+//!
+//! ### Responses
+//!
+//! Each command's response and its components are in `api`, with
+//! accessors for every field. The top-level responses are also
+//! re-exported here. Components which share a name across commands
+//! (e.g. `Route`, `Stop`, `Direction`) are only in their module.
+//!
+//! The routeList, routeConfig and predictions builders take
+//! `use_short_titles`, to have NextBus send short titles in place of
+//! titles. `Agency`, `route_list::Route` and `route_config::Stop`
+//! also pick a `display_title` for a given width, e.g. of a sign.
+//!
+//! With the `serde` feature, every response model implements
+//! Serialize and Deserialize, with the same (camelCase) field names
+//! as the Nextbus attributes.
+//!
+//! Builders for commands with required parameters (e.g. the route
+//! and stop of Predictions, or the first stop of
+//! PredictionsForMultiStops) only have a `get` once they're set, so
+//! leaving one out doesn't compile. `Request` still builds any url
+//! at runtime, failing with `Error::MissingParameter` instead.
+//!
+//! Every builder's setters take it by value and return it, so they
+//! chain from the client, e.g.
+//! `client.route_config().agency("mit").route("boston").get()`.
+//!
+//! Builders borrow their parameters, to be used where they're made.
+//! `RouteListRequest`, `RouteConfigRequest` and `PredictionsRequest`
+//! own theirs instead, so they can be kept (e.g. built from config),
+//! cloned, sent across threads and run repeatedly with a client.
+//!
+//! A response which isn't well formed XML (e.g. its body was cut
+//! short) fails with `Error::Xml`. Each builder's `get_partial`
+//! returns what was parsed before the error instead, as a `Partial`
+//! with the error as a warning.
+//!
+//! ### Async
+//!
+//! With the `async` feature, `AsyncNextBus` wraps a client and
+//! returns futures from its builders, sending requests on a thread
+//! pool.
+//!
+//! ### Fixtures
+//!
+//! `RecordTransport` saves the responses fetched through another
+//! transport into a directory, and `ReplayTransport` serves them
+//! back, so a client can run without the network. The crate's own
+//! tests replay the responses in `fixtures/`; run them with
+//! `NEXTBUS_RECORD=1` to record them again from NextBus.
+//!
+//! ### Mock server
+//!
+//! With the `mock` feature, `MockServer` serves a fixtures directory
+//! over HTTP as the publicXMLFeed endpoint, including NextBus
+//! `<Error>` responses, so a client can be tested end to end against
+//! localhost. The `nextbus-mock-server` binary runs one.
 
 extern crate hyper;
+extern crate rand;
 extern crate xml;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+#[cfg(feature = "async")]
+extern crate futures;
+#[cfg(feature = "async")]
+extern crate futures_cpupool;
 
-mod api;
+pub mod api;
+#[cfg(feature = "async")]
+mod async_client;
+mod cache;
+mod clock;
 mod error;
+mod limit;
+#[cfg(feature = "mock")]
+mod mock;
 mod nb;
 mod request;
+mod retry;
+mod store;
+mod transport;
 
-use api::agency_list::AgencyListBuilder;
-use api::route_list::RouteListBuilder;
+pub use api::{Partial, Set, Unset};
+pub use api::agency_list::{Agency, AgencyList, AgencyListBuilder};
+pub use api::messages::{Messages, MessagesBuilder};
+pub use api::predictions::{Prediction, Predictions, PredictionsBuilder, PredictionsByStopIdBuilder,
+                           PredictionsRequest};
+pub use api::predictions_for_multi_stops::PredictionsForMultiStopsBuilder;
+pub use api::route_config::{RouteConfig, RouteConfigBuilder, RouteConfigRequest};
+pub use api::route_list::{RouteList, RouteListBuilder, RouteListRequest};
+pub use api::schedule::{Schedule, ScheduleBuilder};
+pub use api::vehicle_locations::{Vehicle, VehicleLocations, VehicleLocationsBuilder};
+#[cfg(feature = "async")]
+pub use async_client::{AsyncAgencyListBuilder, AsyncNextBus, AsyncPredictionsBuilder,
+                       AsyncRouteConfigBuilder, AsyncRouteListBuilder};
+pub use cache::{Cache, CacheStore, CachedResponse, DiskStore, MemoryStore};
+pub use clock::{Clock, FakeClock, SystemClock};
 pub use error::{Error, Result};
+pub use limit::{OnLimit, RateLimit, RateLimiter, Slot, Usage};
+#[cfg(feature = "mock")]
+pub use mock::{error_xml, MockListening, MockServer};
+use nb::NEXTBUS_URL;
+pub use request::{Command, Request};
+use request::parse_base_url;
+pub use retry::RetryPolicy;
+use std::collections::HashMap;
+pub use store::{RouteConfigStore, Snapshot};
+use hyper::Url;
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+pub use transport::{FixtureTransport, HyperTransport, RecordTransport, ReplayTransport, Transport};
 
-pub struct NextBus;
+/// Client for the NextBus API. Holds the configuration shared by
+/// every request, and vends a builder for each command which uses it.
+///
+/// - transport: how responses are fetched (HTTP by default). For
+///   a timeout, use `HyperTransport::with_timeout`
+/// - base url: the url requests are built on (NextBus by default)
+/// - agency: used by builders which don't set their own
+/// - retry policy: for all commands, or overridden per command
+/// - rate limit: bandwidth budget, shared by clones of the client
+/// - cache: responses by url, with a TTL per command
+/// - route config store: snapshots to fall back on when offline
+/// - clock: for waiting, the rate limit's window, cache TTLs and
+///   snapshot times
+///
+/// Cloning is cheap, and clones share the same Transport, Clock,
+/// RateLimiter and Cache.
+#[derive(Clone)]
+pub struct NextBus {
+    transport: Arc<Transport>,
+    base_url: String,
+    agency: Option<String>,
+    retry: RetryPolicy,
+    command_retries: HashMap<Command, RetryPolicy>,
+    limiter: Option<Arc<RateLimiter>>,
+    cache: Option<Cache>,
+    route_config_store: Option<RouteConfigStore>,
+    clock: Arc<Clock>,
+}
 
 impl<'a> NextBus {
+    /// Client which fetches from the NextBus url over HTTP.
     pub fn new() -> Self {
-        NextBus
+        NextBus {
+            transport: Arc::new(HyperTransport::new()),
+            base_url: NEXTBUS_URL.to_owned(),
+            agency: None,
+            retry: RetryPolicy::none(),
+            command_retries: HashMap::new(),
+            limiter: None,
+            cache: None,
+            route_config_store: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Replace the url every request is built on, e.g. to use a
+    /// mock server or caching proxy. Checked when a request is sent.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.to_owned();
+        self
+    }
+
+    /// Set the agency for every builder which doesn't set its own.
+    pub fn with_agency(mut self, agency: &str) -> Self {
+        self.agency = Some(agency.to_owned());
+        self
+    }
+
+    /// Replace the transport every request is sent through.
+    pub fn with_transport<T: Transport + 'static>(mut self, transport: T) -> Self {
+        self.transport = Arc::new(transport);
+        self
+    }
+
+    /// Retry failed requests for every command by this policy,
+    /// unless the command has its own.
+    pub fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Retry failed requests for one command by this policy.
+    pub fn with_command_retry(mut self, command: Command, policy: RetryPolicy) -> Self {
+        self.command_retries.insert(command, policy);
+        self
+    }
+
+    /// Limit the bandwidth used by this client and its clones. E.g.
+    /// `RateLimit::nextbus()` for the limit NextBus enforces.
+    /// No limit by default.
+    pub fn with_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.limiter = Some(Arc::new(RateLimiter::new(limit)));
+        self
+    }
+
+    /// Serve responses from the cache while they're fresh, e.g.
+    /// `Cache::memory()` or `Cache::disk(dir)`. No cache by default.
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// When NextBus can't be reached, get route configuration from
+    /// the store's snapshot of the agency instead. Keep it up to date
+    /// with `RouteConfigStore::sync`.
+    pub fn with_route_config_store(mut self, store: RouteConfigStore) -> Self {
+        self.route_config_store = Some(store);
+        self
+    }
+
+    /// Replace the clock, e.g. with a `FakeClock` in tests. It's the
+    /// client's only source of time: it waits between retries and
+    /// for the rate limit, times the rate limit's window, ages the
+    /// cache's responses, and stamps the snapshots synced through
+    /// this client.
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn agency(&self) -> Option<&str> {
+        self.agency.as_ref().map(String::as_str)
+    }
+
+    /// Bandwidth used in the rate limit's current window, if there's
+    /// a rate limit.
+    pub fn usage(&self) -> Option<Usage> {
+        self.limiter.as_ref().map(|limiter| limiter.usage(&*self.clock))
+    }
+
+    /// The cache, e.g. to invalidate responses.
+    pub fn cache(&self) -> Option<&Cache> {
+        self.cache.as_ref()
     }
 
-    pub fn agency_list(self) -> AgencyListBuilder {
-        AgencyListBuilder::new()
+    pub fn route_config_store(&self) -> Option<&RouteConfigStore> {
+        self.route_config_store.as_ref()
     }
 
-    pub fn route_list(self) -> RouteListBuilder<'a> {
-        RouteListBuilder::new()
+    /// Retry policy for a command
+    pub fn retry_policy(&self, command: Command) -> &RetryPolicy {
+        self.command_retries.get(&command).unwrap_or(&self.retry)
+    }
+
+    /// Send a request through this client's transport,
+    /// returning the body of the response. The request is built
+    /// on this client's base url, unless it has its own.
+    ///
+    /// Like the builders' requests, it's served from the cache while
+    /// fresh, retried by the command's policy, and counted towards
+    /// the rate limit. A NextBus `<Error>` fails with
+    /// `Error::ApiError`, and isn't cached.
+    pub fn send(&self, request: &Request) -> Result<Box<Read>> {
+        let res = try!(self.get(request, |mut res: Box<Read>| {
+            let mut body = Vec::new();
+            try!(res.read_to_end(&mut body));
+            try!(api::check_api_error(Cursor::new(&body[..])));
+            Ok(Partial::complete(Box::new(Cursor::new(body)) as Box<Read>))
+        }));
+        Ok(res.into_value())
+    }
+
+    fn build_url(&self, request: &Request) -> Result<Url> {
+        let base_url = try!(parse_base_url(&self.base_url));
+        request.build_url_on(&base_url)
+    }
+
+    // Fetch the whole body through the transport, within the rate limit
+    fn fetch(&self, command: Option<Command>, url: &Url) -> Result<Vec<u8>> {
+        let slot = match self.limiter {
+            Some(ref limiter) => Some(try!(limiter.acquire(command, &*self.clock))),
+            None => None,
+        };
+
+        // Read the whole body, to know its size
+        let mut res = try!(self.transport.fetch(url));
+        let mut body = Vec::new();
+        try!(res.read_to_end(&mut body));
+
+        if let (Some(limiter), Some(slot)) = (self.limiter.as_ref(), slot) {
+            limiter.record(slot, body.len());
+        }
+        Ok(body)
+    }
+
+    // Send a request and parse the response, from the cache if it's
+    // there, retrying by the command's policy otherwise. Parsing is
+    // inside the retry, since that's where NextBus errors are found.
+    //
+    // A response which is only partly parsed (e.g. its body was cut
+    // short) is retried like an error, and never cached. It's
+    // returned if it's still partial on the last attempt.
+    fn get<T, F>(&self, request: &Request, parse: F) -> Result<Partial<T>>
+        where F: Fn(Box<Read>) -> Result<Partial<T>>
+    {
+        let url = try!(self.build_url(request));
+        let command = request.get_command();
+        let policy = match command {
+            Some(command) => self.retry_policy(command).clone(),
+            None => self.retry.clone(),
+        };
+
+        // A cached body which no longer parses is fetched again
+        let cache = match (self.cache.as_ref(), command) {
+            (Some(cache), Some(command)) => Some((cache, command, url.to_string())),
+            _ => None,
+        };
+        if let Some((cache, command, ref key)) = cache {
+            if let Some(body) = cache.get(command, key, &*self.clock) {
+                match parse(Box::new(Cursor::new(body))) {
+                    Ok(ref res) if !res.is_complete() => (),
+                    Ok(res) => return Ok(res),
+                    Err(_) => (),
+                }
+            }
+        }
+
+        let mut attempt = 1;
+        loop {
+            // Body is kept for the cache
+            let res = self.fetch(command, &url).and_then(|body| {
+                let res = try!(parse(Box::new(Cursor::new(body.clone()))));
+                Ok((res, body))
+            });
+            match res {
+                Ok((ref res, _)) if !res.is_complete() && policy.should_retry(attempt) => {
+                    self.clock.sleep(policy.delay(attempt));
+                    attempt += 1;
+                },
+                Ok((res, body)) => {
+                    if res.is_complete() {
+                        if let Some((cache, command, ref key)) = cache {
+                            cache.put(command, key, body, &*self.clock);
+                        }
+                    }
+                    return Ok(res);
+                },
+                Err(ref err) if err.should_retry() && policy.should_retry(attempt) => {
+                    self.clock.sleep(policy.delay(attempt));
+                    attempt += 1;
+                },
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // Agency set on a builder, or else the client's agency.
+    fn agency_or_default<'b>(&'b self, agency: Option<&'b str>) -> Result<&'b str> {
+        agency.or(self.agency()).ok_or(Error::MissingParameter("agency"))
+    }
+
+    // Builders for each command
+    // ===============================================================
+
+    pub fn agency_list(&self) -> AgencyListBuilder {
+        AgencyListBuilder::new(self.clone())
+    }
+
+    pub fn route_list(&self) -> RouteListBuilder<'a> {
+        RouteListBuilder::new(self.clone())
+    }
+
+    pub fn route_config(&self) -> RouteConfigBuilder<'a> {
+        RouteConfigBuilder::new(self.clone())
+    }
+
+    pub fn predictions(&self) -> PredictionsBuilder<'a> {
+        PredictionsBuilder::new(self.clone())
+    }
+
+    pub fn predictions_by_stop_id(&self) -> PredictionsByStopIdBuilder<'a> {
+        PredictionsByStopIdBuilder::new(self.clone())
+    }
+
+    pub fn predictions_for_multi_stops(&self) -> PredictionsForMultiStopsBuilder<'a> {
+        PredictionsForMultiStopsBuilder::new(self.clone())
+    }
+
+    pub fn schedule(&self) -> ScheduleBuilder<'a> {
+        ScheduleBuilder::new(self.clone())
+    }
+
+    pub fn messages(&self) -> MessagesBuilder<'a> {
+        MessagesBuilder::new(self.clone())
+    }
+
+    pub fn vehicle_locations(&self) -> VehicleLocationsBuilder<'a> {
+        VehicleLocationsBuilder::new(self.clone())
     }
 }
 
 #[cfg(test)]
 mod test {
+    use hyper::error::Error as HyperError;
+    use hyper::Url;
+    use std::collections::VecDeque;
+    use std::io::{self, Cursor, Read};
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use transport::fixture_client;
     use super::*;
 
+    const AGENCY_LIST_URL: &'static str = "http://webservices.nextbus.com/service/publicXMLFeed\
+                                           ?command=agencyList";
+
+    const AGENCY_LIST_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright agencies listed below and NextBus Inc 2016.\">
+        <agency tag=\"jhu-apl\" title=\"APL\" regionTitle=\"Maryland\"/>
+        </body>";
+
     #[test]
-    #[ignore]
-    fn get_agency_list() {
+    fn get_agency_list_from_fixture() {
+        let mut fixtures = FixtureTransport::new();
+        fixtures.add(AGENCY_LIST_URL, AGENCY_LIST_XML);
+
         let agencies = NextBus::new()
+            .with_transport(fixtures)
+            .agency_list()
+            .get()
+            .unwrap();
+        assert_eq!(agencies.into_iter().count(), 1);
+    }
+
+    #[test]
+    fn get_agency_list_from_base_url() {
+        let mut fixtures = FixtureTransport::new();
+        fixtures.add("http://localhost:8080/service/publicXMLFeed?command=agencyList",
+                     AGENCY_LIST_XML);
+        let client = NextBus::new()
+            .with_transport(fixtures)
+            .with_base_url("http://localhost:8080/service/publicXMLFeed");
+
+        let agencies = client.agency_list().get().unwrap();
+        assert_eq!(agencies.into_iter().count(), 1);
+
+        // Override for one request
+        let builder = client.agency_list()
+            .base_url("http://proxy.example.com/nextbus/publicXMLFeed");
+        assert!(builder.get().is_err());
+    }
+
+    #[test]
+    fn get_route_list_with_client_agency() {
+        let mut fixtures = FixtureTransport::new();
+        fixtures.add("http://webservices.nextbus.com/service/publicXMLFeed\
+                     ?command=routeList&a=mit", "<body/>");
+        fixtures.add("http://webservices.nextbus.com/service/publicXMLFeed\
+                     ?command=routeList&a=mbta", "<body/>");
+        let client = NextBus::new()
+            .with_transport(fixtures)
+            .with_agency("mit");
+
+        // Uses client's agency, unless the builder sets one
+        assert!(client.route_list().get().is_ok());
+        assert!(client.route_list().agency("mbta").get().is_ok());
+        assert!(client.route_list().agency("bad").get().is_err());
+
+        // No agency at all
+        assert!(NextBus::new().route_list().get().is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn get_route_list_missing_fixture() {
+        NextBus::new()
+            .with_transport(FixtureTransport::new())
+            .route_list()
+            .agency("mbta")
+            .get()
+            .unwrap();
+    }
+
+    const RETRY_ERROR_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright agencies listed below and NextBus Inc 2016.\">
+        <Error shouldRetry=\"true\">
+            Agency server cannot be accessed.
+        </Error>
+        </body>";
+
+    const BAD_AGENCY_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright agencies listed below and NextBus Inc 2016.\">
+        <Error shouldRetry=\"false\">
+            Agency parameter \"a=bad\" is not valid.
+        </Error>
+        </body>";
+
+    // Serves the bodies in order regardless of url, failing like a
+    // network error for None, and once they run out.
+    struct SequenceTransport(Mutex<VecDeque<Option<&'static str>>>);
+
+    impl SequenceTransport {
+        fn new(bodies: Vec<Option<&'static str>>) -> Self {
+            SequenceTransport(Mutex::new(bodies.into_iter().collect()))
+        }
+    }
+
+    impl Transport for SequenceTransport {
+        fn fetch(&self, _: &Url) -> Result<Box<Read>> {
+            match self.0.lock().unwrap().pop_front() {
+                Some(Some(body)) => Ok(Box::new(Cursor::new(body.as_bytes()))),
+                _ => {
+                    let err = io::Error::new(io::ErrorKind::ConnectionRefused, "refused");
+                    Err(HyperError::from(err).into())
+                },
+            }
+        }
+    }
+
+    fn retry_client(bodies: Vec<Option<&'static str>>, clock: &FakeClock) -> NextBus {
+        NextBus::new()
+            .with_transport(SequenceTransport::new(bodies))
+            .with_clock(clock.clone())
+            .with_retry(RetryPolicy::new(3, Duration::from_millis(100)))
+    }
+
+    #[test]
+    fn retry_transport_error() {
+        let clock = FakeClock::new();
+        let client = retry_client(vec![None, None, Some(AGENCY_LIST_XML)], &clock);
+
+        assert!(client.agency_list().get().is_ok());
+        assert_eq!(clock.sleeps(), vec![Duration::from_millis(100),
+                                        Duration::from_millis(200)]);
+    }
+
+    #[test]
+    fn retry_api_error_should_retry() {
+        let clock = FakeClock::new();
+        let client = retry_client(vec![Some(RETRY_ERROR_XML), Some(AGENCY_LIST_XML)], &clock);
+
+        assert!(client.agency_list().get().is_ok());
+        assert_eq!(clock.sleeps(), vec![Duration::from_millis(100)]);
+    }
+
+    #[test]
+    fn no_retry_api_error() {
+        let clock = FakeClock::new();
+        let client = retry_client(vec![Some(BAD_AGENCY_XML), Some(AGENCY_LIST_XML)], &clock);
+
+        match client.route_list().agency("bad").get() {
+            Err(Error::ApiError { should_retry, .. }) => assert!(!should_retry),
+            res => panic!("Expected ApiError, got {:?}", res),
+        }
+        assert!(clock.sleeps().is_empty());
+    }
+
+    #[test]
+    fn retry_gives_up() {
+        let clock = FakeClock::new();
+        let client = retry_client(vec![None, None, None, Some(AGENCY_LIST_XML)], &clock);
+
+        match client.agency_list().get() {
+            Err(Error::HttpError(_)) => (),
+            res => panic!("Expected HttpError, got {:?}", res),
+        }
+        assert_eq!(clock.sleeps().len(), 2);
+    }
+
+    // Body which breaks off with an I/O error
+    struct BrokenBody;
+
+    impl Read for BrokenBody {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::ConnectionReset, "reset"))
+        }
+    }
+
+    struct BrokenTransport;
+
+    impl Transport for BrokenTransport {
+        fn fetch(&self, _: &Url) -> Result<Box<Read>> {
+            Ok(Box::new(BrokenBody))
+        }
+    }
+
+    #[test]
+    fn body_read_error() {
+        let clock = FakeClock::new();
+        let client = NextBus::new()
+            .with_transport(BrokenTransport)
+            .with_clock(clock.clone())
+            .with_retry(RetryPolicy::new(2, Duration::from_millis(100)));
+
+        match client.agency_list().get() {
+            Err(Error::IoError(_)) => (),
+            res => panic!("Expected IoError, got {:?}", res),
+        }
+        assert_eq!(clock.sleeps().len(), 1);
+    }
+
+    #[test]
+    fn retry_per_command() {
+        let clock = FakeClock::new();
+        let client = retry_client(vec![None, Some(AGENCY_LIST_XML), None, Some(AGENCY_LIST_XML)],
+                                  &clock)
+            .with_command_retry(Command::RouteList, RetryPolicy::none());
+
+        // Agency list uses the client's policy, route list its own
+        assert!(client.agency_list().get().is_ok());
+        assert!(client.route_list().agency("mit").get().is_err());
+        assert_eq!(clock.sleeps(), vec![Duration::from_millis(100)]);
+        assert_eq!(client.retry_policy(Command::RouteList), &RetryPolicy::none());
+    }
+
+    #[test]
+    fn rate_limit_shared_by_clones() {
+        let mut fixtures = FixtureTransport::new();
+        fixtures.add(AGENCY_LIST_URL, AGENCY_LIST_XML);
+        let clock = FakeClock::new();
+        let limit = RateLimit::new(Duration::from_secs(20), AGENCY_LIST_XML.len() * 2)
+            .on_limit(OnLimit::Reject);
+        let client = NextBus::new()
+            .with_transport(fixtures)
+            .with_clock(clock.clone())
+            .with_rate_limit(limit);
+
+        assert!(client.agency_list().get().is_ok());
+        assert!(client.clone().agency_list().get().is_ok());
+        let usage = client.usage().unwrap();
+        assert_eq!(usage.requests(), 2);
+        assert_eq!(usage.bytes(), AGENCY_LIST_XML.len() * 2);
+
+        // Over the limit until the window passes
+        match client.agency_list().get() {
+            Err(Error::RateLimitError { wait }) => assert_eq!(wait, Duration::from_secs(20)),
+            res => panic!("Expected RateLimitError, got {:?}", res),
+        }
+        clock.advance(Duration::from_secs(20));
+        assert!(client.agency_list().get().is_ok());
+        assert_eq!(client.usage().unwrap().requests(), 1);
+    }
+
+    #[test]
+    fn cache_serves_fresh_response() {
+        let clock = FakeClock::new();
+        let client = NextBus::new()
+            .with_transport(SequenceTransport::new(vec![Some(AGENCY_LIST_XML),
+                                                        Some(AGENCY_LIST_XML)]))
+            .with_clock(clock.clone())
+            .with_cache(Cache::memory());
+
+        // Second is from the cache, third is fetched once it expires
+        assert!(client.agency_list().get().is_ok());
+        assert!(client.agency_list().get().is_ok());
+        clock.advance(Duration::from_secs(24 * 60 * 60));
+        assert!(client.agency_list().get().is_ok());
+        assert!(client.agency_list().get().is_ok());
+
+        // Nothing left to fetch
+        client.cache().unwrap().invalidate_command(Command::AgencyList);
+        assert!(client.agency_list().get().is_err());
+    }
+
+    #[test]
+    fn cache_skips_api_error() {
+        let client = NextBus::new()
+            .with_transport(SequenceTransport::new(vec![Some(RETRY_ERROR_XML),
+                                                        Some(AGENCY_LIST_XML)]))
+            .with_clock(FakeClock::new())
+            .with_cache(Cache::memory());
+
+        assert!(client.agency_list().get().is_err());
+        assert!(client.agency_list().get().is_ok());
+    }
+
+    #[test]
+    fn send_through_cache() {
+        let client = NextBus::new()
+            .with_transport(SequenceTransport::new(vec![Some(BAD_AGENCY_XML),
+                                                        Some(AGENCY_LIST_XML)]))
+            .with_clock(FakeClock::new())
+            .with_cache(Cache::memory());
+        let mut request = Request::new();
+        request.command(Command::AgencyList);
+
+        // The error isn't cached, the response is
+        match client.send(&request) {
+            Err(Error::ApiError { should_retry, .. }) => assert!(!should_retry),
+            res => panic!("Expected ApiError, got {:?}", res.map(|_| ())),
+        }
+        for _ in 0..2 {
+            let mut body = String::new();
+            client.send(&request).unwrap().read_to_string(&mut body).unwrap();
+            assert_eq!(body, AGENCY_LIST_XML);
+        }
+        assert!(client.agency_list().get().is_ok());
+    }
+
+    #[test]
+    fn partial_response_retried_not_cached() {
+        // Body cut short before its end tag
+        let truncated = &AGENCY_LIST_XML[..AGENCY_LIST_XML.len() - "</body>".len()];
+        let clock = FakeClock::new();
+        let client = retry_client(vec![Some(truncated), Some(truncated), Some(truncated),
+                                       Some(AGENCY_LIST_XML)], &clock)
+            .with_cache(Cache::memory());
+
+        // Partial on every attempt, so it's returned with a warning
+        let partial = client.agency_list().get_partial().unwrap();
+        assert!(!partial.is_complete());
+        assert_eq!(partial.value().into_iter().count(), 1);
+        assert_eq!(clock.sleeps().len(), 2);
+
+        // Fetched again rather than from the cache, then the
+        // complete response is cached
+        assert!(client.agency_list().get().is_ok());
+        assert!(client.agency_list().get().is_ok());
+    }
+
+    #[test]
+    fn get_agency_list() {
+        let agencies = fixture_client()
             .agency_list()
             .get()
             .unwrap();
-        println!("{:?}", agencies);
+        assert_eq!(agencies.into_iter().count(), 6);
     }
 
     #[test]
-    #[ignore]
     fn get_route_list() {
-        let routes = NextBus::new()
+        let routes = fixture_client()
             .route_list()
             .agency("mbta")
             .get()
             .unwrap();
-        println!("{:?}", routes);
+        assert_eq!(routes.into_iter().count(), 3);
     }
 }
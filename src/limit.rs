@@ -0,0 +1,417 @@
+//! Module for limiting the bandwidth used by a client
+//!
+//! NextBus blocks clients which download too much, roughly 2MB of
+//! responses per 20 seconds. A RateLimiter tracks the bytes and
+//! requests sent in a sliding window, and before each request either
+//! waits until the window has room or rejects it.
+//!
+//! Since the size of a response isn't known until it arrives, a
+//! request is let through as long as usage is under the limit, and
+//! its bytes are counted after. It takes its place in the window as
+//! it's let through, so requests sent at once from several threads
+//! can't all pass on the same room.
+
+use clock::Clock;
+use error::Error;
+use request::Command;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// What to do with a request when the limit is reached
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OnLimit {
+    /// Wait until enough of the window has passed
+    Delay,
+    /// Fail with `Error::RateLimitError`
+    Reject,
+}
+
+/// Budget of bytes and requests per window of time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimit {
+    window: Duration,
+    max_bytes: usize,
+    max_requests: Option<usize>,
+    command_max_requests: HashMap<Command, usize>,
+    on_limit: OnLimit,
+}
+
+impl RateLimit {
+    /// Limit of `max_bytes` of responses per `window`, delaying
+    /// requests over the limit.
+    pub fn new(window: Duration, max_bytes: usize) -> Self {
+        RateLimit {
+            window: window,
+            max_bytes: max_bytes,
+            max_requests: None,
+            command_max_requests: HashMap::new(),
+            on_limit: OnLimit::Delay,
+        }
+    }
+
+    /// NextBus's limit of 2MB per 20 seconds
+    pub fn nextbus() -> Self {
+        RateLimit::new(Duration::from_secs(20), 2 * 1024 * 1024)
+    }
+
+    /// Also limit the number of requests per window
+    pub fn with_max_requests(mut self, max_requests: usize) -> Self {
+        self.max_requests = Some(max_requests);
+        self
+    }
+
+    /// Also limit the number of requests per window for one command,
+    /// e.g. `routeConfig`, which NextBus asks not to poll.
+    pub fn with_command_max_requests(mut self, command: Command, max_requests: usize) -> Self {
+        self.command_max_requests.insert(command, max_requests);
+        self
+    }
+
+    /// What to do with a request when the limit is reached
+    pub fn on_limit(mut self, on_limit: OnLimit) -> Self {
+        self.on_limit = on_limit;
+        self
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    pub fn max_requests(&self) -> Option<usize> {
+        self.max_requests
+    }
+
+    pub fn command_max_requests(&self, command: Command) -> Option<usize> {
+        self.command_max_requests.get(&command).cloned()
+    }
+}
+
+/// Bytes and requests sent in the current window
+#[derive(Clone, Debug, PartialEq)]
+pub struct Usage {
+    bytes: usize,
+    requests: usize,
+    window: Duration,
+}
+
+impl Usage {
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn requests(&self) -> usize {
+        self.requests
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+}
+
+// Limiter
+// ===============================================================
+
+/// A request let through by `acquire`. Pass it to `record` once
+/// the response arrives, to count its bytes.
+#[derive(Debug)]
+pub struct Slot {
+    id: usize,
+}
+
+// A request sent in the window. Its bytes are 0 until recorded.
+struct Sent {
+    id: usize,
+    at: Instant,
+    command: Option<Command>,
+    bytes: usize,
+}
+
+// Requests sent in the window, oldest first
+struct Window {
+    sent: VecDeque<Sent>,
+    next_id: usize,
+}
+
+impl Window {
+    fn reserve(&mut self, command: Option<Command>, at: Instant) -> Slot {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.sent.push_back(Sent {
+            id: id,
+            at: at,
+            command: command,
+            bytes: 0,
+        });
+        Slot { id: id }
+    }
+}
+
+/// Tracks usage against a RateLimit. Shared by a client and its
+/// clones, so that every request counts towards the same budget.
+pub struct RateLimiter {
+    limit: RateLimit,
+    window: Mutex<Window>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        RateLimiter {
+            limit: limit,
+            window: Mutex::new(Window {
+                sent: VecDeque::new(),
+                next_id: 0,
+            }),
+        }
+    }
+
+    pub fn limit(&self) -> &RateLimit {
+        &self.limit
+    }
+
+    /// Usage in the window ending now
+    pub fn usage(&self, clock: &Clock) -> Usage {
+        let mut window = self.window.lock().unwrap();
+        self.expire(&mut window.sent, clock.now());
+        Usage {
+            bytes: window.sent.iter().map(|sent| sent.bytes).sum(),
+            requests: window.sent.len(),
+            window: self.limit.window,
+        }
+    }
+
+    /// Before sending a request for the command: wait until it's
+    /// under the limit, or fail if the limit rejects. The request is
+    /// counted from then on, with its bytes once they're recorded.
+    ///
+    /// A budget of zero bytes or requests never has room, so the
+    /// request fails at once rather than waiting forever.
+    pub fn acquire(&self, command: Option<Command>, clock: &Clock) -> ::Result<Slot> {
+        if self.is_zero(command) {
+            return Err(Error::RateLimitError { wait: self.limit.window });
+        }
+
+        loop {
+            let wait = {
+                let mut window = self.window.lock().unwrap();
+                let now = clock.now();
+                self.expire(&mut window.sent, now);
+                match self.wait(&window.sent, command, now) {
+                    Some(wait) => wait,
+                    None => return Ok(window.reserve(command, now)),
+                }
+            };
+
+            match self.limit.on_limit {
+                OnLimit::Delay => clock.sleep(wait),
+                OnLimit::Reject => return Err(Error::RateLimitError { wait: wait }),
+            }
+        }
+    }
+
+    /// After the response to a request let through, count its bytes.
+    /// Nothing is counted if the request has already left the window.
+    pub fn record(&self, slot: Slot, bytes: usize) {
+        let mut window = self.window.lock().unwrap();
+        if let Some(sent) = window.sent.iter_mut().find(|sent| sent.id == slot.id) {
+            sent.bytes = bytes;
+        }
+    }
+
+    // Whether any budget for the command is zero
+    fn is_zero(&self, command: Option<Command>) -> bool {
+        let command_max_requests = command.and_then(|command| {
+            self.limit.command_max_requests(command)
+        });
+        self.limit.max_bytes == 0 ||
+        self.limit.max_requests == Some(0) ||
+        command_max_requests == Some(0)
+    }
+
+    // Drop requests sent before the window
+    fn expire(&self, sent: &mut VecDeque<Sent>, now: Instant) {
+        while sent.front().map_or(false, |sent| sent.at + self.limit.window <= now) {
+            sent.pop_front();
+        }
+    }
+
+    // How long until a request for the command is under the limit,
+    // or None if it already is. Usage only drops as the oldest
+    // requests leave the window, so wait for as many as needed.
+    fn wait(&self, sent: &VecDeque<Sent>, command: Option<Command>, now: Instant) -> Option<Duration> {
+        let command_max_requests = command.and_then(|command| {
+            self.limit.command_max_requests(command)
+        });
+
+        let mut bytes: usize = sent.iter().map(|sent| sent.bytes).sum();
+        let mut requests = sent.len();
+        let mut command_requests = sent.iter().filter(|sent| sent.command == command).count();
+
+        let over = |bytes: usize, requests: usize, command_requests: usize| {
+            bytes >= self.limit.max_bytes ||
+            self.limit.max_requests.map_or(false, |max| requests >= max) ||
+            command_max_requests.map_or(false, |max| command_requests >= max)
+        };
+
+        if !over(bytes, requests, command_requests) {
+            return None;
+        }
+        for oldest in sent {
+            bytes -= oldest.bytes;
+            requests -= 1;
+            if oldest.command == command {
+                command_requests -= 1;
+            }
+            if !over(bytes, requests, command_requests) {
+                return Some(oldest.at + self.limit.window - now);
+            }
+        }
+        Some(self.limit.window)
+    }
+}
+
+// Tests
+// ===============================================================
+
+#[cfg(test)]
+mod test {
+    use clock::FakeClock;
+    use error::Error;
+    use request::Command;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    // Let a request through and count its response
+    fn send(limiter: &RateLimiter, command: Option<Command>, bytes: usize, clock: &FakeClock) {
+        let slot = limiter.acquire(command, clock).unwrap();
+        limiter.record(slot, bytes);
+    }
+
+    #[test]
+    fn usage_in_window() {
+        let clock = FakeClock::new();
+        let limiter = RateLimiter::new(RateLimit::new(secs(20), 1000));
+
+        send(&limiter, Some(Command::Predictions), 100, &clock);
+        clock.advance(secs(10));
+        send(&limiter, Some(Command::Predictions), 200, &clock);
+
+        let usage = limiter.usage(&clock);
+        assert_eq!((usage.bytes(), usage.requests()), (300, 2));
+
+        // First request leaves the window
+        clock.advance(secs(10));
+        let usage = limiter.usage(&clock);
+        assert_eq!((usage.bytes(), usage.requests()), (200, 1));
+    }
+
+    #[test]
+    fn delay_over_bytes() {
+        let clock = FakeClock::new();
+        let limiter = RateLimiter::new(RateLimit::new(secs(20), 1000));
+
+        send(&limiter, None, 600, &clock);
+        clock.advance(secs(5));
+        send(&limiter, None, 600, &clock);
+        clock.advance(secs(5));
+
+        // Under the limit once the first request leaves the window
+        limiter.acquire(None, &clock).unwrap();
+        assert_eq!(clock.sleeps(), vec![secs(10)]);
+        let usage = limiter.usage(&clock);
+        assert_eq!((usage.bytes(), usage.requests()), (600, 2));
+    }
+
+    #[test]
+    fn delay_over_command_requests() {
+        let clock = FakeClock::new();
+        let limit = RateLimit::new(secs(20), 1000)
+            .with_command_max_requests(Command::RouteConfig, 1);
+        let limiter = RateLimiter::new(limit);
+
+        send(&limiter, Some(Command::RouteConfig), 10, &clock);
+        clock.advance(secs(1));
+
+        // Only the limited command waits
+        limiter.acquire(Some(Command::Predictions), &clock).unwrap();
+        assert!(clock.sleeps().is_empty());
+        limiter.acquire(Some(Command::RouteConfig), &clock).unwrap();
+        assert_eq!(clock.sleeps(), vec![secs(19)]);
+    }
+
+    #[test]
+    fn reject_over_requests() {
+        let clock = FakeClock::new();
+        let limit = RateLimit::new(secs(20), 1000)
+            .with_max_requests(2)
+            .on_limit(OnLimit::Reject);
+        let limiter = RateLimiter::new(limit);
+
+        send(&limiter, None, 10, &clock);
+        let slot = limiter.acquire(None, &clock).unwrap();
+
+        // The request counts before its response is recorded
+        match limiter.acquire(None, &clock) {
+            Err(Error::RateLimitError { wait }) => assert_eq!(wait, secs(20)),
+            res => panic!("Expected RateLimitError, got {:?}", res),
+        }
+        assert!(clock.sleeps().is_empty());
+
+        limiter.record(slot, 10);
+        assert_eq!(limiter.usage(&clock).bytes(), 20);
+    }
+
+    #[test]
+    fn zero_limit_fails_without_waiting() {
+        let clock = FakeClock::new();
+        let limit = RateLimit::new(secs(20), 1000)
+            .with_command_max_requests(Command::RouteConfig, 0);
+        let limiter = RateLimiter::new(limit);
+
+        match limiter.acquire(Some(Command::RouteConfig), &clock) {
+            Err(Error::RateLimitError { wait }) => assert_eq!(wait, secs(20)),
+            res => panic!("Expected RateLimitError, got {:?}", res),
+        }
+        assert!(limiter.acquire(Some(Command::Predictions), &clock).is_ok());
+
+        let limiter = RateLimiter::new(RateLimit::new(secs(20), 0));
+        assert!(limiter.acquire(None, &clock).is_err());
+        assert!(clock.sleeps().is_empty());
+    }
+
+    #[test]
+    fn reject_over_requests_across_threads() {
+        let clock = FakeClock::new();
+        let limit = RateLimit::new(secs(20), 1000)
+            .with_max_requests(3)
+            .on_limit(OnLimit::Reject);
+        let limiter = Arc::new(RateLimiter::new(limit));
+
+        // Every thread acquires before any response is recorded
+        let threads: Vec<_> = (0..10).map(|_| {
+            let limiter = limiter.clone();
+            let clock = clock.clone();
+            thread::spawn(move || limiter.acquire(None, &clock).ok())
+        }).collect();
+        let slots: Vec<_> = threads.into_iter()
+            .filter_map(|thread| thread.join().unwrap())
+            .collect();
+
+        assert_eq!(slots.len(), 3);
+        for slot in slots {
+            limiter.record(slot, 100);
+        }
+        let usage = limiter.usage(&clock);
+        assert_eq!((usage.bytes(), usage.requests()), (300, 3));
+    }
+}
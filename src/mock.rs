@@ -0,0 +1,250 @@
+//! Module for a mock NextBus server
+//!
+//! A MockServer serves the publicXMLFeed endpoint over HTTP from a
+//! fixtures directory, named the same way as for ReplayTransport
+//! (e.g. `routeConfig+a=mit+r=boston.xml`). So a client, or an app
+//! built on one, can be run end to end against localhost.
+//!
+//! Like NextBus, problems with a query are answered with an
+//! `<Error>` element in place of a response:
+//!
+//! - no command, or one which isn't a Command
+//! - an agency which has no fixtures at all
+//! - a query with no fixture
+//!
+//! Other errors (e.g. `shouldRetry="true"`) can be served by saving
+//! them as the fixture for a query. Paths other than the feed get a
+//! 404.
+
+use hyper::server::{Listening, Request, Response, Server};
+use hyper::status::StatusCode;
+use hyper::uri::RequestUri;
+use hyper::Url;
+use request::Command;
+use std::fs::{self, File};
+use std::io::Read;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use transport::{fixture_file, sanitize};
+
+/// Path of the feed on the server, the same as on NextBus
+pub const FEED_PATH: &'static str = "/service/publicXMLFeed";
+
+/// Serves the responses saved in a fixtures directory.
+#[derive(Clone, Debug)]
+pub struct MockServer {
+    dir: PathBuf,
+}
+
+impl MockServer {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        MockServer {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Listen on the address, e.g. "127.0.0.1:0" for any free port,
+    /// answering each request on a thread pool.
+    pub fn listen<A: ToSocketAddrs>(self, addr: A) -> ::Result<MockListening> {
+        let server = try!(Server::http(addr));
+        let listening = try!(server.handle(move |req: Request, mut res: Response| {
+            let (status, body) = match req.uri {
+                RequestUri::AbsolutePath(ref path) => self.respond(path),
+                _ => (StatusCode::BadRequest, String::new()),
+            };
+            *res.status_mut() = status;
+            let _ = res.send(body.as_bytes());
+        }));
+        Ok(MockListening {
+            addr: listening.socket,
+            listening: Some(listening),
+        })
+    }
+
+    /// Status and body of the response for a path and query,
+    /// e.g. "/service/publicXMLFeed?command=agencyList".
+    pub fn respond(&self, path: &str) -> (StatusCode, String) {
+        let url = match Url::parse(&format!("http://localhost{}", path)) {
+            Ok(url) => url,
+            Err(_) => return (StatusCode::BadRequest, String::new()),
+        };
+        if url.serialize_path().as_ref().map(String::as_str) != Some(FEED_PATH) {
+            return (StatusCode::NotFound, String::new());
+        }
+
+        let pairs = url.query_pairs().unwrap_or(Vec::new());
+        let param = |key: &str| {
+            pairs.iter().find(|pair| pair.0 == key).map(|pair| pair.1.clone())
+        };
+
+        let command = match param("command") {
+            Some(command) => command,
+            None => return (StatusCode::Ok,
+                            error_xml("command parameter must be specified in query string",
+                                      false)),
+        };
+        if command.parse::<Command>().is_err() {
+            return (StatusCode::Ok,
+                    error_xml(&format!("Command \"{}\" is not valid.", command), false));
+        }
+
+        let mut body = String::new();
+        let found = File::open(self.dir.join(fixture_file(&url)))
+            .and_then(|mut file| file.read_to_string(&mut body));
+        if found.is_ok() {
+            return (StatusCode::Ok, body);
+        }
+
+        let message = match param("a") {
+            Some(ref agency) if !self.has_agency(agency) => {
+                format!("Agency parameter \"a={}\" is not valid.", agency)
+            },
+            _ => format!("No data for query \"{}\".", url.query.unwrap_or(String::new())),
+        };
+        (StatusCode::Ok, error_xml(&message, false))
+    }
+
+    // Whether any fixture is for the agency
+    fn has_agency(&self, agency: &str) -> bool {
+        let param = format!("+a={}+", sanitize(agency));
+        let last_param = format!("+a={}.xml", sanitize(agency));
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return false,
+        };
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .any(|name| name.contains(&param) || name.ends_with(&last_param))
+    }
+}
+
+/// A MockServer listening for requests. Stops accepting them once
+/// closed or dropped.
+pub struct MockListening {
+    addr: SocketAddr,
+    listening: Option<Listening>,
+}
+
+impl MockListening {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Url of the feed, to use as a client's base url.
+    pub fn base_url(&self) -> String {
+        format!("http://{}{}", self.addr, FEED_PATH)
+    }
+
+    pub fn close(&mut self) {
+        if let Some(mut listening) = self.listening.take() {
+            let _ = listening.close();
+        }
+    }
+}
+
+impl Drop for MockListening {
+    // Listening joins its threads on drop, which would never return
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Body of an `<Error>` response, the way NextBus sends it.
+pub fn error_xml(message: &str, should_retry: bool) -> String {
+    format!("<?xml version=\"1.0\" encoding=\"utf-8\" ?>\n\
+             <body copyright=\"All data copyright agencies listed below and NextBus Inc 2016.\">\n\
+             <Error shouldRetry=\"{}\">\n{}\n</Error>\n\
+             </body>\n",
+            should_retry,
+            escape(message))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Tests
+// ===============================================================
+
+#[cfg(test)]
+mod test {
+    use error::Error;
+    use hyper::status::StatusCode;
+    use NextBus;
+    use super::*;
+
+    fn fixtures_server() -> MockServer {
+        MockServer::new(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures"))
+    }
+
+    #[test]
+    fn responds_with_fixture() {
+        let (status, body) = fixtures_server().respond("/service/publicXMLFeed\
+                                                        ?command=routeList&a=mit");
+        assert_eq!(status, StatusCode::Ok);
+        assert!(body.contains("saferidecampshut"));
+    }
+
+    #[test]
+    fn responds_with_errors() {
+        let server = fixtures_server();
+
+        let (status, body) = server.respond("/service/publicXMLFeed?command=bad");
+        assert_eq!(status, StatusCode::Ok);
+        assert!(body.contains("Command &quot;bad&quot; is not valid."));
+
+        let (_, body) = server.respond("/service/publicXMLFeed?a=mit");
+        assert!(body.contains("<Error shouldRetry=\"false\">"));
+
+        let (status, _) = server.respond("/other?command=agencyList");
+        assert_eq!(status, StatusCode::NotFound);
+    }
+
+    #[test]
+    fn serves_every_command() {
+        let listening = fixtures_server().listen("127.0.0.1:0").unwrap();
+        let client = NextBus::new().with_base_url(&listening.base_url());
+
+        assert_eq!(client.agency_list().get().unwrap().into_iter().count(), 6);
+        assert_eq!(client.route_list().agency("mit").get().unwrap().into_iter().count(), 4);
+        assert!(client.route_config().agency("mit").route("saferidecampshut").get().is_ok());
+        assert!(client.predictions()
+            .agency("mit")
+            .route("saferidecampshut")
+            .stop("mass84_d")
+            .get()
+            .is_ok());
+        assert!(client.predictions_by_stop_id().agency("sf-muni").stop_id("14448").get().is_ok());
+        assert!(client.predictions_for_multi_stops()
+            .agency("mbta")
+            .stop("1", "110")
+            .stop("47", "1123")
+            .get()
+            .is_ok());
+        assert!(client.schedule().agency("mit").route("saferidecampshut").get().is_ok());
+        assert!(client.messages().agency("sf-muni").route("N").route("J").get().is_ok());
+        assert!(client.vehicle_locations().agency("sf-muni").route("N").time(0).get().is_ok());
+    }
+
+    #[test]
+    fn serves_api_errors() {
+        let listening = fixtures_server().listen("127.0.0.1:0").unwrap();
+        let client = NextBus::new().with_base_url(&listening.base_url());
+
+        match client.route_list().agency("bad").get() {
+            Err(Error::ApiError { message, should_retry }) => {
+                assert_eq!(message, "Agency parameter \"a=bad\" is not valid.");
+                assert!(!should_retry);
+            },
+            res => panic!("Expected ApiError, got {:?}", res),
+        }
+    }
+}
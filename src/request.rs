@@ -2,7 +2,9 @@
 //!
 //! Builder pattern is used to create a Request
 //!
-//! The Request is guaranteed to be valid!
+//! The Request is guaranteed to be valid! Building its url fails if
+//! its command is missing a parameter, or given one it doesn't take
+//! (or more than once).
 //!
 //! ## Components
 //! - Command
@@ -17,11 +19,13 @@
 // TODO: Change API to get rid of add_route and add_stop,
 // just use route and stop, and always append instead of replace?
 
+use error::Error;
 use nb::NEXTBUS_URL;
-use hyper::client::Client;
-use hyper::client::response::Response;
 use hyper::Url;
 use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
+use transport::Transport;
 
 /// A valid next bus url and query
 /// Verified on building (dynamic). Because tuple struct constructors
@@ -34,30 +38,48 @@ impl NextBusApiCall {
 
 // Request Builder
 
-#[derive(Debug, PartialEq)]
-pub struct Request<'a> {
+/// Owns its parameters, so a request can be kept (e.g. built once
+/// from config), cloned and sent across threads.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Request {
+    base_url: Option<String>,
     command: Option<Command>,
-    agency: Option<&'a str>,
-    routes: Option<Vec<&'a str>>,
-    stops:Option< Vec<&'a str>>,
-    time: Option<usize>,
+    agency: Option<String>,
+    routes: Option<Vec<String>>,
+    stops: Option<Vec<String>>,
+    stop_id: Option<String>,
+    time: Option<u64>,
+    terse: bool,
+    use_short_titles: bool,
 }
 
 /// Build a Next Bus Request!
 /// Last invocation of each method is the
 /// one that "sticks"
 // TODO: pass args as reference?
-impl<'a> Request<'a> {
+impl Request {
     pub fn new() -> Self {
         Request {
+            base_url: None,
             command: None,
             agency: None,
             routes: None,
             stops: None,
+            stop_id: None,
             time: None,
+            terse: false,
+            use_short_titles: false,
         }
     }
 
+    /// Base url for the request, overriding the one it's built on
+    /// (e.g. a mock server or caching proxy). Last invocation is
+    /// the one that will be built.
+    pub fn base_url(&mut self, base_url: &str) -> &mut Self {
+        self.base_url = Some(base_url.to_owned());
+        self
+    }
+
     /// Command for Next Bus. Last invocation is the one that
     /// will be built.
     pub fn command(&mut self, command: Command) -> &mut Self {
@@ -65,38 +87,44 @@ impl<'a> Request<'a> {
         self
     }
 
+    /// The command set so far, if any.
+    pub fn get_command(&self) -> Option<Command> {
+        self.command
+    }
+
     /// Chose an agency. Last invocation is the one that will be
     /// built.
-    pub fn agency(&mut self, agency: &'a str) -> &mut Self {
-        self.agency = Some(agency);
+    pub fn agency(&mut self, agency: &str) -> &mut Self {
+        self.agency = Some(agency.to_owned());
         self
     }
 
     /// Chose a route. Last invocation is the one that will be
     /// built.
-    pub fn route(&mut self, route: &'a str) -> &mut Self {
-        self.routes = Some(vec![route]);
+    pub fn route(&mut self, route: &str) -> &mut Self {
+        self.routes = Some(vec![route.to_owned()]);
         self
     }
 
     /// Append a route. Last invocation is the one that will be
     /// built.
-    pub fn add_route(&mut self, route: &'a str) -> &mut Self {
+    pub fn add_route(&mut self, route: &str) -> &mut Self {
         //TODO: Better way to initialize?
         if self.routes.is_none() { self.routes = Some(vec![]); }
-        self.routes.as_mut().map(|routes| routes.push(route));
+        self.routes.as_mut().map(|routes| routes.push(route.to_owned()));
         self
     }
 
     /// Chose a list of routes. Reaplces any and all previous
     /// routes in the list
-    pub fn routes(&mut self, routes: Vec<&'a str>) -> &mut Self {
-        self.routes = Some(routes);
+    pub fn routes(&mut self, routes: Vec<&str>) -> &mut Self {
+        self.routes = Some(to_owned_all(routes));
         self
     }
 
     /// Append a list of routes to the current list of routes
-    pub fn append_routes(&mut self, mut routes: Vec<&'a str>) -> &mut Self {
+    pub fn append_routes(&mut self, routes: Vec<&str>) -> &mut Self {
+        let mut routes = to_owned_all(routes);
         self.routes.as_mut().map(|mut current_routes| {
             Vec::append(&mut current_routes, &mut routes);
         });
@@ -105,57 +133,91 @@ impl<'a> Request<'a> {
 
     /// Chose a stop. Last invocation is the one that will be
     /// built. Replaces any and all previous stops in the list.
-    pub fn stop(&mut self, stop: &'a str) -> &mut Self {
-        self.stops = Some(vec![stop]);
+    pub fn stop(&mut self, stop: &str) -> &mut Self {
+        self.stops = Some(vec![stop.to_owned()]);
         self
     }
 
     /// Append a stop to the current list of stops.
-    pub fn add_stop(&mut self, stop: &'a str) -> &mut Self {
+    pub fn add_stop(&mut self, stop: &str) -> &mut Self {
         //TODO: Better way to initialize?
         if self.stops.is_none() {self.stops = Some(vec![]); }
-        self.stops.as_mut().map(|stops| stops.push(stop));
+        self.stops.as_mut().map(|stops| stops.push(stop.to_owned()));
         self
     }
 
     /// Chose a list of stops. Replaces any and all previous
     /// stops in the list.
-    pub fn stops(&mut self, stops: Vec<&'a str>) -> &mut Self {
-        self.stops = Some(stops);
+    pub fn stops(&mut self, stops: Vec<&str>) -> &mut Self {
+        self.stops = Some(to_owned_all(stops));
         self
     }
 
     /// Append a list of stops to the current list of stops.
     // Not entirely clear why this can't be &mut in fn defn
-    pub fn append_stops(&mut self, mut stops: Vec<&'a str>) -> &mut Self {
+    pub fn append_stops(&mut self, stops: Vec<&str>) -> &mut Self {
+        let mut stops = to_owned_all(stops);
         self.stops.as_mut().map(|mut current_stops| {
             Vec::append(&mut current_stops, &mut stops);
         });
         self
     }
 
+    /// Chose a stop by its agency-wide id (e.g. the number on the
+    /// stop's sign), for predictions on every route serving it.
+    /// A route, if any, then only narrows them down to that route.
+    pub fn stop_id(&mut self, stop_id: &str) -> &mut Self {
+        self.stop_id = Some(stop_id.to_owned());
+        self
+    }
+
     /// Chose a time. Replaces previous any previous time.
-    pub fn time(&mut self, time: usize) -> &mut Self {
+    pub fn time(&mut self, time: u64) -> &mut Self {
         self.time = Some(time);
         self
     }
 
+    /// Leave the paths out of a route config.
+    pub fn terse(&mut self) -> &mut Self {
+        self.terse = true;
+        self
+    }
+
+    /// Have NextBus send each short title in place of the title,
+    /// where there is one.
+    pub fn use_short_titles(&mut self) -> &mut Self {
+        self.use_short_titles = true;
+        self
+    }
+
+    /// Build the url on the NextBus url, unless the request has
+    /// its own base url.
     pub fn build_url(&self) -> ::Result<Url> {
+        let base_url = try!(parse_base_url(NEXTBUS_URL));
+        self.build_url_on(&base_url)
+    }
+
+    /// Build the url on the given base url, unless the request has
+    /// its own base url.
+    pub fn build_url_on(&self, base_url: &Url) -> ::Result<Url> {
 
         // build url query params
         let mut queries = vec![];
 
-        queries.push(("command", self.command.as_ref().unwrap().to_string()));
+        let command = try!(self.command.ok_or(Error::MissingParameter("command")));
+        try!(self.validate(command));
+        let by_stop_id = self.stop_id.is_some();
+        queries.push(("command", command.to_string()));
 
-        if self.agency.is_some() {
-            queries.push(("a", self.agency.unwrap().to_owned()));
+        if let Some(ref agency) = self.agency {
+            queries.push(("a", agency.clone()));
         }
 
         if self.routes.is_some() {
             let mut route_queries: Vec<_> = self.routes.as_ref().unwrap()
                 .iter()
                 .map(|route| {
-                    ("r", route.to_string())
+                    (route_param(command, by_stop_id), route.to_string())
                 })
                 .collect();
             Vec::append(&mut queries, &mut route_queries);
@@ -164,40 +226,142 @@ impl<'a> Request<'a> {
         if self.stops.is_some() {
             let mut stop_queries: Vec<_> = self.stops.as_ref().unwrap()
                 .iter()
-                .map(|stop| {
-                    if self.command == Some(Command::Predictions) {
-                        ("s", stop.to_string())
-                    } else {
-                        ("stops", stop.to_string())
-                    }
-                })
+                .map(|stop| (stop_param(command), stop.to_string()))
                 .collect();
             Vec::append(&mut queries, &mut stop_queries);
         }
 
+        if let Some(ref stop_id) = self.stop_id {
+            queries.push(("stopId", stop_id.clone()));
+        }
+
         if self.time.is_some() {
             queries.push(("t", self.time.unwrap().to_string()));
         }
 
+        if self.terse {
+            queries.push(("terse", "true".to_owned()));
+        }
+
+        if self.use_short_titles {
+            queries.push(("useShortTitles", "true".to_owned()));
+        }
+
         // Create url
-        let mut url = Url::parse(NEXTBUS_URL).unwrap();
+        let mut url = match self.base_url {
+            Some(ref base_url) => try!(parse_base_url(base_url)),
+            None => base_url.clone(),
+        };
         url.set_query_from_pairs(queries);
 
         Ok(url)
     }
 
-    // TODO: should take self, not &self?
-    pub fn send(&self) -> ::Result<Response> {
-        let client = Client::new();
+    // Check the number of values of each param against what the
+    // command takes.
+    fn validate(&self, command: Command) -> ::Result<()> {
+        let by_stop_id = self.stop_id.is_some();
+        let counts = [
+            ("a", self.agency.iter().count()),
+            (route_param(command, by_stop_id), self.routes.as_ref().map_or(0, Vec::len)),
+            (stop_param(command), self.stops.as_ref().map_or(0, Vec::len)),
+            ("stopId", self.stop_id.iter().count()),
+            ("t", self.time.iter().count()),
+            ("terse", self.terse as usize),
+            ("useShortTitles", self.use_short_titles as usize),
+        ];
+
+        for &(param, count) in counts.iter() {
+            let (min, max) = params(command, by_stop_id)
+                .iter()
+                .find(|&&(name, _)| name == param)
+                .map_or(NONE, |&(_, occurs)| occurs);
+
+            if count < min {
+                return Err(Error::MissingParameter(param));
+            }
+            if count > max {
+                return Err(if max == 0 {
+                    Error::UnexpectedParameter { command: command, param: param }
+                } else {
+                    Error::RepeatedParameter { command: command, param: param }
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Build the url and fetch it through the transport,
+    /// returning the body of the response.
+    pub fn send(&self, transport: &Transport) -> ::Result<Box<Read>> {
         let url = try!(self.build_url());
-        let res = try!(client.get(url).send());
-        Ok(res)
+        transport.fetch(&url)
+    }
+}
+
+// Parameters
+// ===============================================================
+
+// How many values of a param a command takes, as (min, max)
+type Occurs = (usize, usize);
+
+const NONE: Occurs = (0, 0);
+const OPTIONAL: Occurs = (0, 1);
+const ONE: Occurs = (1, 1);
+const ANY: Occurs = (0, ::std::usize::MAX);
+const MANY: Occurs = (1, ::std::usize::MAX);
+
+// Params each command takes. Any other is unexpected. Predictions
+// are either for a route and stop tag, or for a stop id.
+fn params(command: Command, by_stop_id: bool) -> &'static [(&'static str, Occurs)] {
+    match command {
+        Command::AgencyList => &[],
+        Command::RouteList => &[("a", ONE), ("useShortTitles", OPTIONAL)],
+        Command::RouteConfig => {
+            &[("a", ONE), ("r", OPTIONAL), ("terse", OPTIONAL), ("useShortTitles", OPTIONAL)]
+        },
+        Command::Predictions if by_stop_id => {
+            &[("a", ONE), ("routeTag", OPTIONAL), ("stopId", ONE), ("useShortTitles", OPTIONAL)]
+        },
+        Command::Predictions => {
+            &[("a", ONE), ("r", ONE), ("s", ONE), ("useShortTitles", OPTIONAL)]
+        },
+        Command::PredictionsForMultiStops => &[("a", ONE), ("stops", MANY)],
+        Command::Schedule => &[("a", ONE), ("r", ONE)],
+        Command::Messages => &[("a", ONE), ("r", ANY)],
+        Command::VehicleLocations => &[("a", ONE), ("r", OPTIONAL), ("t", ONE)],
+    }
+}
+
+// Predictions by stop id take their route as "routeTag".
+fn route_param(command: Command, by_stop_id: bool) -> &'static str {
+    match command {
+        Command::Predictions if by_stop_id => "routeTag",
+        _ => "r",
+    }
+}
+
+// Stops are "s" for predictions, and "route|stop" pairs in
+// "stops" otherwise.
+fn stop_param(command: Command) -> &'static str {
+    match command {
+        Command::Predictions => "s",
+        _ => "stops",
     }
 }
 
+fn to_owned_all(values: Vec<&str>) -> Vec<String> {
+    values.into_iter().map(str::to_owned).collect()
+}
+
+/// Parse a base url, e.g. for a mock server or caching proxy.
+pub fn parse_base_url(base_url: &str) -> ::Result<Url> {
+    Url::parse(base_url).map_err(|_| Error::BuildUrlError)
+}
+
 // Components for building the NextBus Url
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum Command {
     AgencyList,
     RouteList,
@@ -224,227 +388,475 @@ impl fmt::Display for Command {
     }
 }
 
+impl FromStr for Command {
+    type Err = Error;
+
+    /// Command from its name in the NextBus query, e.g. "routeConfig"
+    fn from_str(s: &str) -> ::Result<Command> {
+        match s {
+            "agencyList" => Ok(Command::AgencyList),
+            "routeList" => Ok(Command::RouteList),
+            "routeConfig" => Ok(Command::RouteConfig),
+            "predictions" => Ok(Command::Predictions),
+            "predictionsForMultiStops" => Ok(Command::PredictionsForMultiStops),
+            "schedule" => Ok(Command::Schedule),
+            "messages" => Ok(Command::Messages),
+            "vehicleLocations" => Ok(Command::VehicleLocations),
+            _ => Err(Error::InvalidCommand(s.to_owned())),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use std::io::Read;
+    use std::io::{Cursor, Read};
     use super::*;
     use hyper::Url;
+    use transport::Transport;
+
+    // Answers with the url it was asked for, to check what was sent.
+    struct EchoTransport;
+
+    impl Transport for EchoTransport {
+        fn fetch(&self, url: &Url) -> ::Result<Box<Read>> {
+            Ok(Box::new(Cursor::new(url.to_string().into_bytes())))
+        }
+    }
+
+    fn sent_url(mut res: Box<Read>) -> Url {
+        let mut body = String::new();
+        res.read_to_string(&mut body).unwrap();
+        Url::parse(&body).unwrap()
+    }
+
+    // Urls are checked on the NextBus url and on other bases,
+    // like a mock server or caching proxy.
+    const BASE_URLS: [&'static str; 3] = [
+        "http://webservices.nextbus.com/service/publicXMLFeed",
+        "http://localhost:8080/service/publicXMLFeed",
+        "http://proxy.example.com/nextbus/publicXMLFeed",
+    ];
+
+    fn expected_url(base_url: &str, query: &str) -> Url {
+        Url::parse(&format!("{}?{}", base_url, query)).unwrap()
+    }
 
     #[test]
     fn builds_agency_list() {
-        let res_url = Request::new()
-            .command(Command::AgencyList)
-            .build_url()
-            .unwrap();
-        let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed\
-                             ?command=agencyList").unwrap();
-        assert_eq!(res_url, url);
+        for base_url in BASE_URLS.iter() {
+            let base = parse_base_url(base_url).unwrap();
+
+            let res_url = Request::new()
+                .command(Command::AgencyList)
+                .build_url_on(&base)
+                .unwrap();
+            let url = expected_url(base_url, "command=agencyList");
+            assert_eq!(res_url, url);
+        }
     }
 
     #[test]
     fn builds_routes_list() {
-        let res_url = Request::new()
-            .command(Command::RouteList)
-            .agency("test_agency")
-            .build_url()
-            .unwrap();
-        let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
-                             command=routeList&a=test_agency").unwrap();
-        assert_eq!(res_url, url);
+        for base_url in BASE_URLS.iter() {
+            let base = parse_base_url(base_url).unwrap();
+
+            let res_url = Request::new()
+                .command(Command::RouteList)
+                .agency("test_agency")
+                .build_url_on(&base)
+                .unwrap();
+            let url = expected_url(base_url, "command=routeList&a=test_agency");
+            assert_eq!(res_url, url);
+        }
     }
 
     #[test]
     fn builds_route_config() {
-        // for one route
-        let res_url = Request::new()
-            .command(Command::RouteConfig)
-            .agency("test_agency")
-            .route("one")
-            .build_url()
-            .unwrap();
-        let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
-                             command=routeConfig&a=test_agency&r=one").unwrap();
-        assert_eq!(res_url, url);
-
-        // for many routes
-        let res_url = Request::new()
-            .command(Command::RouteConfig)
-            .agency("test_agency")
-            .build_url()
-            .unwrap();
-        let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
-                             command=routeConfig&a=test_agency").unwrap();
-        assert_eq!(res_url, url);
+        for base_url in BASE_URLS.iter() {
+            let base = parse_base_url(base_url).unwrap();
+
+            // for one route
+            let res_url = Request::new()
+                .command(Command::RouteConfig)
+                .agency("test_agency")
+                .route("one")
+                .build_url_on(&base)
+                .unwrap();
+            let url = expected_url(base_url, "command=routeConfig&a=test_agency&r=one");
+            assert_eq!(res_url, url);
+
+            // for many routes
+            let res_url = Request::new()
+                .command(Command::RouteConfig)
+                .agency("test_agency")
+                .build_url_on(&base)
+                .unwrap();
+            let url = expected_url(base_url, "command=routeConfig&a=test_agency");
+            assert_eq!(res_url, url);
+
+            // without paths
+            let res_url = Request::new()
+                .command(Command::RouteConfig)
+                .agency("test_agency")
+                .route("one")
+                .terse()
+                .build_url_on(&base)
+                .unwrap();
+            let url = expected_url(base_url, "command=routeConfig&a=test_agency&r=one&terse=true");
+            assert_eq!(res_url, url);
+        }
     }
 
     #[test]
     fn builds_predictions() {
-        let res_url = Request::new()
-            .command(Command::Predictions)
-            .agency("test_agency")
-            .route("one")
-            .stop("stop_1")
-            .build_url()
-            .unwrap();
-        let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
-                             command=predictions&a=test_agency&r=one&s=stop_1").unwrap();
-        assert_eq!(res_url, url);
+        for base_url in BASE_URLS.iter() {
+            let base = parse_base_url(base_url).unwrap();
+
+            let res_url = Request::new()
+                .command(Command::Predictions)
+                .agency("test_agency")
+                .route("one")
+                .stop("stop_1")
+                .build_url_on(&base)
+                .unwrap();
+            let url = expected_url(base_url, "command=predictions&a=test_agency&r=one&s=stop_1");
+            assert_eq!(res_url, url);
+        }
     }
 
     #[test]
-    fn builds_predictions_for_multi_stops() {
-        //normal
+    fn builds_with_short_titles() {
         let res_url = Request::new()
-            .command(Command::PredictionsForMultiStops)
+            .command(Command::RouteList)
             .agency("test_agency")
-            .stop("stop_1")
-            .add_stop("stop_2")
+            .use_short_titles()
             .build_url()
             .unwrap();
-        let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
-                             command=predictionsForMultiStops&a=test_agency\
-                             &stops=stop_1&stops=stop_2").unwrap();
+        let url = expected_url(BASE_URLS[0],
+                               "command=routeList&a=test_agency&useShortTitles=true");
         assert_eq!(res_url, url);
+    }
 
-        // using add_stop first
-        let res_url = Request::new()
-            .command(Command::PredictionsForMultiStops)
-            .agency("test_agency")
-            .add_stop("stop_1")
-            .add_stop("stop_2")
-            .build_url()
-            .unwrap();
-        let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
-                             command=predictionsForMultiStops&a=test_agency\
-                             &stops=stop_1&stops=stop_2").unwrap();
-        assert_eq!(res_url, url);
+    #[test]
+    fn builds_predictions_by_stop_id() {
+        for base_url in BASE_URLS.iter() {
+            let base = parse_base_url(base_url).unwrap();
+
+            let res_url = Request::new()
+                .command(Command::Predictions)
+                .agency("test_agency")
+                .stop_id("1234")
+                .build_url_on(&base)
+                .unwrap();
+            let url = expected_url(base_url, "command=predictions&a=test_agency&stopId=1234");
+            assert_eq!(res_url, url);
+
+            // for one of its routes
+            let res_url = Request::new()
+                .command(Command::Predictions)
+                .agency("test_agency")
+                .route("one")
+                .stop_id("1234")
+                .build_url_on(&base)
+                .unwrap();
+            let url = expected_url(base_url, "command=predictions&a=test_agency\
+                                 &routeTag=one&stopId=1234");
+            assert_eq!(res_url, url);
+        }
+    }
+
+    #[test]
+    fn builds_predictions_for_multi_stops() {
+        for base_url in BASE_URLS.iter() {
+            let base = parse_base_url(base_url).unwrap();
+
+            //normal
+            let res_url = Request::new()
+                .command(Command::PredictionsForMultiStops)
+                .agency("test_agency")
+                .stop("stop_1")
+                .add_stop("stop_2")
+                .build_url_on(&base)
+                .unwrap();
+            let url = expected_url(base_url, "command=predictionsForMultiStops&a=test_agency\
+                                 &stops=stop_1&stops=stop_2");
+            assert_eq!(res_url, url);
+
+            // using add_stop first
+            let res_url = Request::new()
+                .command(Command::PredictionsForMultiStops)
+                .agency("test_agency")
+                .add_stop("stop_1")
+                .add_stop("stop_2")
+                .build_url_on(&base)
+                .unwrap();
+            let url = expected_url(base_url, "command=predictionsForMultiStops&a=test_agency\
+                                 &stops=stop_1&stops=stop_2");
+            assert_eq!(res_url, url);
+        }
     }
 
     #[test]
     fn builds_schedule() {
-        let res_url = Request::new()
-            .command(Command::Schedule)
-            .agency("test_agency")
-            .route("one")
-            .build_url()
-            .unwrap();
-        let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
-                             command=schedule&a=test_agency&r=one").unwrap();
-        assert_eq!(res_url, url);
+        for base_url in BASE_URLS.iter() {
+            let base = parse_base_url(base_url).unwrap();
+
+            let res_url = Request::new()
+                .command(Command::Schedule)
+                .agency("test_agency")
+                .route("one")
+                .build_url_on(&base)
+                .unwrap();
+            let url = expected_url(base_url, "command=schedule&a=test_agency&r=one");
+            assert_eq!(res_url, url);
+        }
     }
 
     #[test]
     fn builds_messages() {
-        //normal, one route
+        for base_url in BASE_URLS.iter() {
+            let base = parse_base_url(base_url).unwrap();
+
+            //normal, one route
+            let res_url = Request::new()
+                .command(Command::Messages)
+                .agency("test_agency")
+                .route("one")
+                .build_url_on(&base)
+                .unwrap();
+            let url = expected_url(base_url, "command=messages&a=test_agency&r=one");
+            assert_eq!(res_url, url);
+
+            // multiple routes
+            let res_url = Request::new()
+                .command(Command::Messages)
+                .agency("test_agency")
+                .route("one")
+                .add_route("two")
+                .build_url_on(&base)
+                .unwrap();
+            let url = expected_url(base_url, "command=messages&a=test_agency&r=one&r=two");
+            assert_eq!(res_url, url);
+        }
+    }
+
+    #[test]
+    fn builds_vehicle_locations() {
+        for base_url in BASE_URLS.iter() {
+            let base = parse_base_url(base_url).unwrap();
+
+            let res_url = Request::new()
+                .command(Command::VehicleLocations)
+                .agency("test_agency")
+                .route("one")
+                .time(0)
+                .build_url_on(&base)
+                .unwrap();
+            let url = expected_url(base_url, "command=vehicleLocations&a=test_agency&r=one&t=0");
+            assert_eq!(res_url, url);
+        }
+    }
+
+    #[test]
+    fn builds_on_request_base_url() {
+        // Request base url overrides the one it's built on
+        let base = parse_base_url(BASE_URLS[0]).unwrap();
         let res_url = Request::new()
-            .command(Command::Messages)
-            .agency("test_agency")
-            .route("one")
-            .build_url()
+            .base_url(BASE_URLS[1])
+            .command(Command::AgencyList)
+            .build_url_on(&base)
             .unwrap();
-        let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
-                             command=messages&a=test_agency&r=one").unwrap();
-        assert_eq!(res_url, url);
+        assert_eq!(res_url, expected_url(BASE_URLS[1], "command=agencyList"));
 
-        // multiple routes
+        // Default is the NextBus url
         let res_url = Request::new()
-            .command(Command::Messages)
-            .agency("test_agency")
-            .route("one")
-            .add_route("two")
+            .command(Command::AgencyList)
             .build_url()
             .unwrap();
-        let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
-                             command=messages&a=test_agency&r=one&r=two").unwrap();
-        assert_eq!(res_url, url);
+        assert_eq!(res_url, expected_url(BASE_URLS[0], "command=agencyList"));
     }
 
     #[test]
-    fn builds_vehicle_locations() {
-        let res_url = Request::new()
-            .command(Command::VehicleLocations)
-            .agency("test_agency")
-            .route("one")
-            .time(0)
+    #[should_panic]
+    fn builds_on_bad_base_url() {
+        Request::new()
+            .base_url("not a url")
+            .command(Command::AgencyList)
             .build_url()
             .unwrap();
-        let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
-                             command=vehicleLocations&a=test_agency&r=one&t=0").unwrap();
-        assert_eq!(res_url, url);
     }
 
     #[test]
-    #[ignore]
+    fn validates_params_for_each_command() {
+        use self::Command::*;
+
+        // Request for the command with the given params
+        fn request(command: Command, params: &[(&str, &str)]) -> Request {
+            let mut request = Request::new();
+            request.command(command);
+            for &(param, value) in params {
+                match param {
+                    "a" => { request.agency(value); },
+                    "r" => { request.add_route(value); },
+                    "s" => { request.add_stop(value); },
+                    "stopId" => { request.stop_id(value); },
+                    "t" => { request.time(value.parse().unwrap()); },
+                    "terse" => { request.terse(); },
+                    "useShortTitles" => { request.use_short_titles(); },
+                    _ => unreachable!(),
+                }
+            }
+            request
+        }
+
+        // Each command with valid params, then missing, unexpected
+        // and repeated ones
+        let cases: Vec<(Command, Vec<(&str, &str)>, Result<(), &str>)> = vec![
+            (AgencyList, vec![], Ok(())),
+            (AgencyList, vec![("a", "mit")], Err("Unexpected Parameter: a for agencyList")),
+
+            (RouteList, vec![("a", "mit")], Ok(())),
+            (RouteList, vec![("a", "mit"), ("useShortTitles", "true")], Ok(())),
+            (RouteList, vec![], Err("Missing Parameter: a")),
+            (RouteList, vec![("a", "mit"), ("r", "1")],
+             Err("Unexpected Parameter: r for routeList")),
+            (RouteList, vec![("a", "mit"), ("terse", "true")],
+             Err("Unexpected Parameter: terse for routeList")),
+
+            (RouteConfig, vec![("a", "mit")], Ok(())),
+            (RouteConfig, vec![("a", "mit"), ("r", "1")], Ok(())),
+            (RouteConfig, vec![("a", "mit"), ("r", "1"), ("useShortTitles", "true")], Ok(())),
+            (RouteConfig, vec![("a", "mit"), ("terse", "true")], Ok(())),
+            (RouteConfig, vec![("a", "mit"), ("r", "1"), ("r", "2")],
+             Err("Repeated Parameter: r for routeConfig")),
+            (RouteConfig, vec![("a", "mit"), ("t", "0")],
+             Err("Unexpected Parameter: t for routeConfig")),
+
+            (Predictions, vec![("a", "mit"), ("r", "1"), ("s", "110")], Ok(())),
+            (Predictions, vec![("a", "mit"), ("s", "110")], Err("Missing Parameter: r")),
+            (Predictions, vec![("a", "mit"), ("r", "1")], Err("Missing Parameter: s")),
+            (Predictions, vec![("a", "mit"), ("r", "1"), ("s", "110"), ("s", "111")],
+             Err("Repeated Parameter: s for predictions")),
+            (Predictions, vec![("a", "mit"), ("r", "1"), ("s", "110"), ("t", "0")],
+             Err("Unexpected Parameter: t for predictions")),
+            (Predictions, vec![("a", "mit"), ("stopId", "57")], Ok(())),
+            (Predictions, vec![("a", "mit"), ("r", "1"), ("stopId", "57")], Ok(())),
+            (Predictions, vec![("a", "mit"), ("stopId", "57"), ("useShortTitles", "true")],
+             Ok(())),
+            (Predictions, vec![("a", "mit"), ("r", "1"), ("s", "110"), ("useShortTitles", "true")],
+             Ok(())),
+            (Predictions, vec![("a", "mit"), ("r", "1"), ("r", "2"), ("stopId", "57")],
+             Err("Repeated Parameter: routeTag for predictions")),
+            (Predictions, vec![("a", "mit"), ("s", "110"), ("stopId", "57")],
+             Err("Unexpected Parameter: s for predictions")),
+
+            (PredictionsForMultiStops, vec![("a", "mbta"), ("s", "1|110")], Ok(())),
+            (PredictionsForMultiStops, vec![("a", "mbta"), ("s", "1|110"), ("s", "47|1123")],
+             Ok(())),
+            (PredictionsForMultiStops, vec![("a", "mbta")], Err("Missing Parameter: stops")),
+            (PredictionsForMultiStops, vec![("a", "mbta"), ("r", "1"), ("s", "1|110")],
+             Err("Unexpected Parameter: r for predictionsForMultiStops")),
+
+            (Schedule, vec![("a", "mit"), ("r", "1")], Ok(())),
+            (Schedule, vec![], Err("Missing Parameter: a")),
+            (Schedule, vec![("a", "mit")], Err("Missing Parameter: r")),
+            (Schedule, vec![("a", "mit"), ("r", "1"), ("r", "2")],
+             Err("Repeated Parameter: r for schedule")),
+            (Schedule, vec![("a", "mit"), ("r", "1"), ("useShortTitles", "true")],
+             Err("Unexpected Parameter: useShortTitles for schedule")),
+
+            (Messages, vec![("a", "mit")], Ok(())),
+            (Messages, vec![("a", "mit"), ("r", "1"), ("r", "2")], Ok(())),
+            (Messages, vec![("a", "mit"), ("s", "110")],
+             Err("Unexpected Parameter: stops for messages")),
+            (Messages, vec![("a", "mit"), ("stopId", "57")],
+             Err("Unexpected Parameter: stopId for messages")),
+
+            (VehicleLocations, vec![("a", "mit"), ("t", "0")], Ok(())),
+            (VehicleLocations, vec![("a", "mit"), ("r", "1"), ("t", "0")], Ok(())),
+            (VehicleLocations, vec![("a", "mit"), ("r", "1")], Err("Missing Parameter: t")),
+            (VehicleLocations, vec![("a", "mit"), ("r", "1"), ("r", "2"), ("t", "0")],
+             Err("Repeated Parameter: r for vehicleLocations")),
+        ];
+
+        for (command, params, expected) in cases {
+            let res = request(command, &params).build_url()
+                .map(|_| ())
+                .map_err(|err| err.to_string());
+            assert_eq!(res, expected.map_err(str::to_owned), "{} with {:?}", command, params);
+        }
+    }
+
+    #[test]
+    fn builds_without_command() {
+        match Request::new().agency("mit").build_url() {
+            Err(Error::MissingParameter(param)) => assert_eq!(param, "command"),
+            res => panic!("Expected MissingParameter, got {:?}", res),
+        }
+    }
+
+    #[test]
     fn gets_agency_list() {
-        let mut res = Request::new()
+        let res = Request::new()
             .command(Command::AgencyList)
-            .send()
+            .send(&EchoTransport)
             .unwrap();
         let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed\
                              ?command=agencyList").unwrap();
-        assert_eq!(res.url, url);
-        let mut body = String::new();
-        res.read_to_string(&mut body).unwrap();
-        println!("{:?}", res);
-        println!("\n");
-        println!("{:?}", body);
+        assert_eq!(sent_url(res), url);
     }
 
     #[test]
-    #[ignore]
     fn gets_routes_list() {
         let res = Request::new()
             .command(Command::RouteList)
             .agency("test_agency")
-            .send()
+            .send(&EchoTransport)
             .unwrap();
         let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
                              command=routeList&a=test_agency").unwrap();
-        assert_eq!(res.url, url);
+        assert_eq!(sent_url(res), url);
     }
 
     #[test]
-    #[ignore]
     fn gets_route_config() {
         // for one route
         let res = Request::new()
             .command(Command::RouteConfig)
             .agency("test_agency")
             .route("one")
-            .send()
+            .send(&EchoTransport)
             .unwrap();
         let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
                              command=routeConfig&a=test_agency&r=one").unwrap();
-        assert_eq!(res.url, url);
+        assert_eq!(sent_url(res), url);
 
         // for many routes
         let res = Request::new()
             .command(Command::RouteConfig)
             .agency("test_agency")
-            .send()
+            .send(&EchoTransport)
             .unwrap();
         let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
                              command=routeConfig&a=test_agency").unwrap();
-        assert_eq!(res.url, url);
+        assert_eq!(sent_url(res), url);
     }
 
     #[test]
-    #[ignore]
     fn gets_predictions() {
         let res = Request::new()
             .command(Command::Predictions)
             .agency("test_agency")
             .route("one")
             .stop("stop_1")
-            .send()
+            .send(&EchoTransport)
             .unwrap();
         let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
                              command=predictions&a=test_agency&r=one&s=stop_1").unwrap();
-        assert_eq!(res.url, url);
+        assert_eq!(sent_url(res), url);
     }
 
     #[test]
-    #[ignore]
     fn gets_predictions_for_multi_stops() {
         //normal
         let res = Request::new()
@@ -452,12 +864,12 @@ mod test {
             .agency("test_agency")
             .stop("stop_1")
             .add_stop("stop_2")
-            .send()
+            .send(&EchoTransport)
             .unwrap();
         let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
                              command=predictionsForMultiStops&a=test_agency\
                              &stops=stop_1&stops=stop_2").unwrap();
-        assert_eq!(res.url, url);
+        assert_eq!(sent_url(res), url);
 
         // using add_stop first
         let res = Request::new()
@@ -465,41 +877,39 @@ mod test {
             .agency("test_agency")
             .add_stop("stop_1")
             .add_stop("stop_2")
-            .send()
+            .send(&EchoTransport)
             .unwrap();
         let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
                              command=predictionsForMultiStops&a=test_agency\
                              &stops=stop_1&stops=stop_2").unwrap();
-        assert_eq!(res.url, url);
+        assert_eq!(sent_url(res), url);
     }
 
     #[test]
-    #[ignore]
     fn gets_schedule() {
         let res = Request::new()
             .command(Command::Schedule)
             .agency("test_agency")
             .route("one")
-            .send()
+            .send(&EchoTransport)
             .unwrap();
         let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
                              command=schedule&a=test_agency&r=one").unwrap();
-        assert_eq!(res.url, url);
+        assert_eq!(sent_url(res), url);
     }
 
     #[test]
-    #[ignore]
     fn gets_messages() {
         //normal, one route
         let res = Request::new()
             .command(Command::Messages)
             .agency("test_agency")
             .route("one")
-            .send()
+            .send(&EchoTransport)
             .unwrap();
         let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
                              command=messages&a=test_agency&r=one").unwrap();
-        assert_eq!(res.url, url);
+        assert_eq!(sent_url(res), url);
 
         // multiple routes
         let res = Request::new()
@@ -507,25 +917,24 @@ mod test {
             .agency("test_agency")
             .route("one")
             .add_route("two")
-            .send()
+            .send(&EchoTransport)
             .unwrap();
         let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
                              command=messages&a=test_agency&r=one&r=two").unwrap();
-        assert_eq!(res.url, url);
+        assert_eq!(sent_url(res), url);
     }
 
     #[test]
-    #[ignore]
     fn gets_vehicle_locations() {
         let res = Request::new()
             .command(Command::VehicleLocations)
             .agency("test_agency")
             .route("one")
             .time(0)
-            .send()
+            .send(&EchoTransport)
             .unwrap();
         let url = Url::parse("http://webservices.nextbus.com/service/publicXMLFeed?\
                              command=vehicleLocations&a=test_agency&r=one&t=0").unwrap();
-        assert_eq!(res.url, url);
+        assert_eq!(sent_url(res), url);
     }
 }
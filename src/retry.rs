@@ -0,0 +1,163 @@
+//! Module for retrying failed requests
+//!
+//! A RetryPolicy decides how many times a request is attempted, and
+//! how long to wait between attempts. The wait doubles after each
+//! failed attempt (exponential backoff), up to a max, and jitter
+//! shortens it by a random amount so that many clients retrying at
+//! once don't stay in lockstep.
+//!
+//! Only errors which may succeed later are retried (see
+//! `Error::should_retry`).
+
+use rand;
+use std::time::Duration;
+
+/// How to retry a request. Defaults to no retries.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: f64,
+}
+
+impl RetryPolicy {
+    /// Policy making up to `max_attempts` attempts (including the
+    /// first), waiting `base_delay` after the first failure and
+    /// doubling after each one. Waits are capped at 60 seconds.
+    pub fn new(max_attempts: u32, base_delay: Duration) -> Self {
+        RetryPolicy {
+            max_attempts: max_attempts,
+            base_delay: base_delay,
+            max_delay: Duration::from_secs(60),
+            jitter: 0.0,
+        }
+    }
+
+    /// Policy making one attempt only
+    pub fn none() -> Self {
+        RetryPolicy::new(1, Duration::from_secs(0))
+    }
+
+    /// Cap for the wait between attempts
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Fraction (from 0 to 1) of each wait which may randomly be cut.
+    /// With 0.5, a wait of 1s lasts between 0.5s and 1s.
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.max(0.0).min(1.0);
+        self
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub fn base_delay(&self) -> Duration {
+        self.base_delay
+    }
+
+    pub fn max_delay(&self) -> Duration {
+        self.max_delay
+    }
+
+    pub fn jitter(&self) -> f64 {
+        self.jitter
+    }
+
+    /// Whether another attempt is allowed after `attempt` (starting
+    /// at 1) has failed.
+    pub fn should_retry(&self, attempt: u32) -> bool {
+        attempt < self.max_attempts
+    }
+
+    /// How long to wait after `attempt` (starting at 1) has failed.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.delay_with_random(attempt, rand::random())
+    }
+
+    // `random` is from 0 to 1, and picks how much jitter to cut.
+    fn delay_with_random(&self, attempt: u32, random: f64) -> Duration {
+        // Doubling stops once it would pass the max anyways
+        let mut delay = self.base_delay;
+        for _ in 1..attempt {
+            if delay >= self.max_delay {
+                break;
+            }
+            delay = delay * 2;
+        }
+        if delay > self.max_delay {
+            delay = self.max_delay;
+        }
+
+        let cut = secs_to_duration(duration_to_secs(delay) * self.jitter * random);
+        delay - cut
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::none()
+    }
+}
+
+fn duration_to_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
+
+fn secs_to_duration(secs: f64) -> Duration {
+    Duration::new(secs.trunc() as u64, (secs.fract() * 1e9) as u32)
+}
+
+// Tests
+// ===============================================================
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use super::*;
+
+    #[test]
+    fn delay_doubles() {
+        let policy = RetryPolicy::new(5, Duration::from_millis(100));
+        let delays: Vec<_> = (1..5).map(|attempt| policy.delay(attempt)).collect();
+        assert_eq!(delays, vec![Duration::from_millis(100),
+                                Duration::from_millis(200),
+                                Duration::from_millis(400),
+                                Duration::from_millis(800)]);
+    }
+
+    #[test]
+    fn delay_capped() {
+        let policy = RetryPolicy::new(50, Duration::from_secs(1))
+            .with_max_delay(Duration::from_secs(5));
+        assert_eq!(policy.delay(3), Duration::from_secs(4));
+        assert_eq!(policy.delay(4), Duration::from_secs(5));
+        assert_eq!(policy.delay(40), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn delay_with_jitter() {
+        let policy = RetryPolicy::new(5, Duration::from_secs(1)).with_jitter(0.5);
+        assert_eq!(policy.delay_with_random(1, 0.0), Duration::from_secs(1));
+        assert_eq!(policy.delay_with_random(1, 1.0), Duration::from_millis(500));
+        assert_eq!(policy.delay_with_random(2, 0.5), Duration::from_millis(1500));
+
+        for _ in 0..100 {
+            let delay = policy.delay(1);
+            assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn attempts() {
+        let policy = RetryPolicy::new(3, Duration::from_secs(1));
+        assert!(policy.should_retry(1));
+        assert!(policy.should_retry(2));
+        assert!(!policy.should_retry(3));
+        assert!(!RetryPolicy::none().should_retry(1));
+    }
+}
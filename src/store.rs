@@ -0,0 +1,373 @@
+//! Module for keeping route configuration offline
+//!
+//! A RouteConfigStore keeps a snapshot of each synced agency's full
+//! RouteConfig (routes, stops, directions and paths) on disk, one
+//! file per agency. The snapshot can be loaded without a connection,
+//! and says when it was synced so callers know how stale it is.
+//!
+//! Given to a client, the RouteConfigBuilder falls back on the
+//! snapshot when NextBus can't be reached.
+//!
+//! A routeConfig request without a route only returns the first
+//! 100 routes, so a sync gets the agency's routes from routeList,
+//! then the routeConfig of each.
+//!
+//! The file has the version on the first line, the time it was
+//! synced (secs since the epoch) on the second, the agency on the
+//! third, then the raw routeConfig response of each route, each
+//! after a line with its length in bytes.
+
+use api::route_config::{self, Route, RouteConfig, Stop};
+use clock::Clock;
+use error::Error;
+use request::{Command, Request};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use NextBus;
+
+/// Snapshots of route configuration, by agency, in a directory.
+#[derive(Clone, Debug)]
+pub struct RouteConfigStore {
+    dir: PathBuf,
+}
+
+impl RouteConfigStore {
+    /// Store in the directory, creating it if missing.
+    pub fn new<P: AsRef<Path>>(dir: P) -> ::Result<Self> {
+        try!(fs::create_dir_all(dir.as_ref()));
+        Ok(RouteConfigStore {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Fetch the route configuration for every route of the agency,
+    /// and replace its snapshot. The previous snapshot is kept if
+    /// any fetch fails.
+    ///
+    /// Responses always come from NextBus, never the client's cache,
+    /// so a snapshot is as new as the sync.
+    pub fn sync(&self, client: &NextBus, agency: &str) -> ::Result<Snapshot> {
+        let mut client = client.clone();
+        client.cache = None;
+
+        let route_list = try!(client.route_list().agency(agency).get());
+
+        // Check each parses before replacing anything
+        let mut bodies = Vec::new();
+        let mut routes = Vec::new();
+        for route in route_list {
+            let mut res = try!(client.send(Request::new()
+                .command(Command::RouteConfig)
+                .agency(agency)
+                .route(route.tag())));
+            let mut body = Vec::new();
+            try!(res.read_to_end(&mut body));
+            routes.extend(try!(route_config::parse_route_config(&body[..])));
+            bodies.push(body);
+        }
+
+        let version = self.load(agency).map(|snapshot| snapshot.version + 1).unwrap_or(1);
+        let synced_at = client.clock.system_time();
+        try!(self.write(agency, version, synced_at, &bodies));
+
+        Ok(Snapshot {
+            agency: agency.to_owned(),
+            version: version,
+            synced_at: synced_at,
+            route_config: RouteConfig::new(routes),
+        })
+    }
+
+    /// Snapshot of the agency, from the last sync.
+    pub fn load(&self, agency: &str) -> ::Result<Snapshot> {
+        let path = self.path(agency);
+        let mut file = BufReader::new(try!(File::open(&path)));
+
+        let version = try!(read_header(&mut file, &path));
+        let synced_at = try!(read_header(&mut file, &path));
+        let mut stored_agency = String::new();
+        try!(file.read_line(&mut stored_agency));
+        if stored_agency.trim_right() != agency {
+            return Err(Error::CorruptFile(path));
+        }
+
+        // Each route's response, until the end of the file
+        let mut routes = Vec::new();
+        while !try!(file.fill_buf()).is_empty() {
+            let len = try!(read_header(&mut file, &path));
+            let mut body = vec![0; len as usize];
+            try!(file.read_exact(&mut body).map_err(|_| Error::CorruptFile(path.clone())));
+            routes.extend(try!(route_config::parse_route_config(&body[..])));
+        }
+
+        Ok(Snapshot {
+            agency: agency.to_owned(),
+            version: version,
+            synced_at: UNIX_EPOCH + Duration::from_secs(synced_at),
+            route_config: RouteConfig::new(routes),
+        })
+    }
+
+    /// Remove the agency's snapshot
+    pub fn remove(&self, agency: &str) -> ::Result<()> {
+        Ok(try!(fs::remove_file(self.path(agency))))
+    }
+
+    fn path(&self, agency: &str) -> PathBuf {
+        // Agency tags are plain, but keep them from leaving the dir
+        let name: String = agency.chars()
+            .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        self.dir.join(format!("{}.routeconfig", name))
+    }
+
+    // Written next to the snapshot then moved over it, so that a
+    // snapshot is never left half written.
+    fn write(&self,
+             agency: &str,
+             version: u64,
+             synced_at: SystemTime,
+             bodies: &[Vec<u8>]) -> ::Result<()> {
+        let synced_at = synced_at.duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or(0);
+
+        let path = self.path(agency);
+        let tmp_path = path.with_extension("routeconfig.tmp");
+        {
+            let mut file = try!(File::create(&tmp_path));
+            try!(write!(file, "{}\n{}\n{}\n", version, synced_at, agency));
+            for body in bodies {
+                try!(write!(file, "{}\n", body.len()));
+                try!(file.write_all(body));
+            }
+        }
+        Ok(try!(fs::rename(tmp_path, path)))
+    }
+}
+
+fn read_header<R: BufRead>(file: &mut R, path: &Path) -> ::Result<u64> {
+    let mut line = String::new();
+    try!(file.read_line(&mut line));
+    line.trim().parse().map_err(|_| Error::CorruptFile(path.to_path_buf()))
+}
+
+// Snapshot
+// ===============================================================
+
+/// An agency's route configuration, as of a sync.
+///
+/// `version` counts the syncs of the agency, starting at 1.
+#[derive(Debug)]
+pub struct Snapshot {
+    agency: String,
+    version: u64,
+    synced_at: SystemTime,
+    route_config: RouteConfig,
+}
+
+impl Snapshot {
+    pub fn agency(&self) -> &str {
+        &self.agency
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn synced_at(&self) -> SystemTime {
+        self.synced_at
+    }
+
+    /// Time since the sync by the clock (e.g. the client's), or zero
+    /// if it's in the future.
+    pub fn age(&self, clock: &Clock) -> Duration {
+        clock.system_time().duration_since(self.synced_at).unwrap_or(Duration::from_secs(0))
+    }
+
+    pub fn route_config(&self) -> &RouteConfig {
+        &self.route_config
+    }
+
+    pub fn into_route_config(self) -> RouteConfig {
+        self.route_config
+    }
+
+    /// Look up a route by tag
+    pub fn route(&self, tag: &str) -> Option<&Route> {
+        (&self.route_config).into_iter().find(|route| route.tag() == tag)
+    }
+
+    /// Look up a stop by tag, on any route
+    pub fn stop(&self, tag: &str) -> Option<&Stop> {
+        (&self.route_config).into_iter()
+            .flat_map(|route| route.stops())
+            .find(|stop| stop.tag() == tag)
+    }
+}
+
+// Tests
+// ===============================================================
+
+#[cfg(test)]
+mod test {
+    use cache::Cache;
+    use clock::FakeClock;
+    use error::Error;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use transport::FixtureTransport;
+    use NextBus;
+    use super::*;
+
+    const URL: &'static str = "http://webservices.nextbus.com/service/publicXMLFeed";
+
+    const ROUTE_LIST_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright Massachusetts Institute of Technology 2016.\">
+        <route tag=\"boston\" title=\"Boston Daytime\"/>
+        <route tag=\"tech\" title=\"Tech Shuttle\"/>
+        </body>";
+
+    const BOSTON_ROUTE_CONFIG_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright Massachusetts Institute of Technology 2016.\">
+        <route tag=\"boston\" title=\"Boston Daytime\" color=\"9999ff\"
+            oppositeColor=\"000000\" latMin=\"42.3398\" latMax=\"42.3617\"
+            lonMin=\"-71.1166\" lonMax=\"-71.0716\">
+        <stop tag=\"massave\" title=\"84 Mass Ave\" lat=\"42.3592\" lon=\"-71.0938\"/>
+        </route>
+        </body>";
+
+    const TECH_ROUTE_CONFIG_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright Massachusetts Institute of Technology 2016.\">
+        <route tag=\"tech\" title=\"Tech Shuttle\" color=\"ff0000\"
+            oppositeColor=\"ffffff\" latMin=\"42.3555\" latMax=\"42.3624\"
+            lonMin=\"-71.1032\" lonMax=\"-71.0856\">
+        <stop tag=\"kendsq\" title=\"Kendall Square T\" lat=\"42.3625\" lon=\"-71.0861\"/>
+        </route>
+        </body>";
+
+    const ERROR_XML: &'static str = "
+        <?xml version=\"1.0\" encoding=\"utf-8\" ?>
+        <body copyright=\"All data copyright agencies listed below and NextBus Inc 2016.\">
+        <Error shouldRetry=\"false\">
+            Agency parameter \"a=mit\" is not valid.
+        </Error>
+        </body>";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("nextbus-store-{}-{}", name, ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    // Client serving mit's route list and route configs, with the
+    // tech route's body given
+    fn client(tech_body: &str, clock: &FakeClock) -> NextBus {
+        let mut fixtures = FixtureTransport::new();
+        fixtures.add(&format!("{}?command=routeList&a=mit", URL), ROUTE_LIST_XML);
+        fixtures.add(&format!("{}?command=routeConfig&a=mit&r=boston", URL),
+                     BOSTON_ROUTE_CONFIG_XML);
+        fixtures.add(&format!("{}?command=routeConfig&a=mit&r=tech", URL), tech_body);
+        NextBus::new().with_transport(fixtures).with_clock(clock.clone())
+    }
+
+    #[test]
+    fn sync_and_load() {
+        let dir = temp_dir("sync");
+        let store = RouteConfigStore::new(&dir).unwrap();
+        let clock = FakeClock::new();
+        let client = client(TECH_ROUTE_CONFIG_XML, &clock);
+
+        assert_eq!(store.sync(&client, "mit").unwrap().version(), 1);
+        clock.advance(Duration::from_secs(60 * 60));
+        let synced = store.sync(&client, "mit").unwrap();
+        assert_eq!(synced.version(), 2);
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(synced.age(&clock), Duration::from_secs(60));
+
+        let snapshot = store.load("mit").unwrap();
+        assert_eq!(snapshot.agency(), "mit");
+        assert_eq!(snapshot.version(), 2);
+        assert_eq!(snapshot.synced_at().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+                   synced.synced_at().duration_since(UNIX_EPOCH).unwrap().as_secs());
+        let tags: Vec<_> = snapshot.route_config().into_iter().map(|route| route.tag()).collect();
+        assert_eq!(tags, vec!["boston", "tech"]);
+        assert_eq!(snapshot.route("tech").unwrap().title(), "Tech Shuttle");
+        assert_eq!(snapshot.stop("kendsq").unwrap().title(), "Kendall Square T");
+        assert!(snapshot.route("bad").is_none());
+
+        store.remove("mit").unwrap();
+        assert!(store.load("mit").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sync_skips_cache() {
+        let dir = temp_dir("cache");
+        let store = RouteConfigStore::new(&dir).unwrap();
+        let clock = FakeClock::new();
+        let cache = Cache::memory();
+
+        store.sync(&client(TECH_ROUTE_CONFIG_XML, &clock).with_cache(cache.clone()), "mit")
+            .unwrap();
+
+        // Same urls, new route config, well within the cache's TTL
+        let renamed = TECH_ROUTE_CONFIG_XML.replace("Tech Shuttle", "Tech Express");
+        clock.advance(Duration::from_secs(60));
+        let synced = store.sync(&client(&renamed, &clock).with_cache(cache), "mit").unwrap();
+        assert_eq!(synced.route("tech").unwrap().title(), "Tech Express");
+        assert_eq!(store.load("mit").unwrap().route("tech").unwrap().title(), "Tech Express");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sync_keeps_snapshot_on_error() {
+        let dir = temp_dir("error");
+        let store = RouteConfigStore::new(&dir).unwrap();
+        let clock = FakeClock::new();
+
+        store.sync(&client(TECH_ROUTE_CONFIG_XML, &clock), "mit").unwrap();
+
+        // Fails on the second route
+        match store.sync(&client(ERROR_XML, &clock), "mit") {
+            Err(Error::ApiError { .. }) => (),
+            res => panic!("Expected ApiError, got {:?}", res),
+        }
+        assert_eq!(store.load("mit").unwrap().version(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn route_config_offline() {
+        let dir = temp_dir("offline");
+        let store = RouteConfigStore::new(&dir).unwrap();
+        let clock = FakeClock::new();
+        store.sync(&client(TECH_ROUTE_CONFIG_XML, &clock), "mit").unwrap();
+
+        // No fixtures, so every request fails like a network error
+        let offline = NextBus::new()
+            .with_transport(FixtureTransport::new())
+            .with_route_config_store(store);
+
+        let routes = offline.route_config().agency("mit").get().unwrap();
+        assert_eq!(routes.into_iter().count(), 2);
+        let routes = offline.route_config().agency("mit").route("tech").get().unwrap();
+        let tags: Vec<_> = routes.into_iter().map(|route| route.tag().to_owned()).collect();
+        assert_eq!(tags, vec!["tech"]);
+
+        // Nothing synced for the agency
+        assert!(offline.route_config().agency("mbta").get().is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
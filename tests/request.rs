@@ -0,0 +1,42 @@
+//! Requests built and sent from outside the crate, the way an app
+//! would for a url the builders don't cover.
+
+extern crate nextbus;
+
+use nextbus::{Command, Error, FixtureTransport, NextBus, Request};
+use std::io::Read;
+
+#[test]
+fn build_url() {
+    let mut request = Request::new();
+    request.command(Command::RouteConfig).agency("mit").route("boston");
+    assert_eq!(request.build_url().unwrap().to_string(),
+               "http://webservices.nextbus.com/service/publicXMLFeed\
+                ?command=routeConfig&a=mit&r=boston");
+}
+
+#[test]
+fn build_url_missing_parameter() {
+    let mut request = Request::new();
+    request.command(Command::RouteConfig).route("boston");
+    match request.build_url() {
+        Err(Error::MissingParameter(param)) => assert_eq!(param, "a"),
+        res => panic!("Expected MissingParameter, got {:?}", res),
+    }
+}
+
+#[test]
+fn send_with_client() {
+    let mut fixtures = FixtureTransport::new();
+    fixtures.add("http://localhost:8080/service/publicXMLFeed?command=agencyList",
+                 "<body/>");
+    let client = NextBus::new()
+        .with_transport(fixtures)
+        .with_base_url("http://localhost:8080/service/publicXMLFeed");
+
+    let mut request = Request::new();
+    request.command(Command::AgencyList);
+    let mut body = String::new();
+    client.send(&request).unwrap().read_to_string(&mut body).unwrap();
+    assert_eq!(body, "<body/>");
+}